@@ -0,0 +1,481 @@
+use image::{
+    codecs::{
+        gif::{GifDecoder, GifEncoder, Repeat},
+        png::PngDecoder,
+        webp::{WebPDecoder, WebPEncoder},
+    },
+    AnimationDecoder, Delay, ExtendedColorType, Frame,
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Cursor},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+#[derive(Clone, Copy)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    WebP,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopCount {
+    Infinite,
+    Plays(u32),
+}
+
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    pub loop_count: LoopCount,
+}
+
+/// A range of frame indices written like `10..40`, either end can be left out
+#[derive(Clone, Copy)]
+pub struct FrameRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once("..").ok_or_else(|| {
+            format!("Invalid frame range \"{s}\", expected something like 10..40")
+        })?;
+        let parse = |number: &str| {
+            number
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid frame number \"{number}\""))
+        };
+        let range = Self {
+            start: if start.is_empty() { 0 } else { parse(start)? },
+            end: if end.is_empty() {
+                None
+            } else {
+                Some(parse(end)?)
+            },
+        };
+        match range.end {
+            Some(end) if end <= range.start => Err(format!(
+                "Invalid frame range \"{s}\", the end has to come after the start"
+            )),
+            _ => Ok(range),
+        }
+    }
+}
+
+impl Animation {
+    /// Keeps every `every`th frame inside `range`, the delays of skipped frames are added to the
+    /// kept frame before them so the animation plays at the same pace
+    pub fn select_frames(&mut self, range: Option<FrameRange>, every: usize) {
+        let (start, end) = match range {
+            Some(range) => (range.start, range.end.unwrap_or(usize::MAX)),
+            None => (0, usize::MAX),
+        };
+        let mut kept: Vec<Frame> = vec![];
+        for (index, frame) in self.frames.drain(..).enumerate() {
+            if index < start || index >= end {
+                continue;
+            }
+            if (index - start) % every == 0 {
+                kept.push(frame);
+            } else if let Some(last) = kept.pop() {
+                let delay = Duration::from(last.delay()) + Duration::from(frame.delay());
+                let (left, top) = (last.left(), last.top());
+                kept.push(Frame::from_parts(
+                    last.into_buffer(),
+                    left,
+                    top,
+                    Delay::from_saturating_duration(delay),
+                ));
+            }
+        }
+        if kept.is_empty() {
+            panic!("No frames left to paint after applying the frame range");
+        }
+        self.frames = kept;
+    }
+}
+
+/// Opens a directory of frames, a glob pattern or an animated file, still images return `None`
+pub fn open(path: &str, fps: f32) -> Option<Animation> {
+    if let Some(paths) = sequence(path) {
+        Some(decode_sequence(&paths, fps))
+    } else {
+        detect(path).map(|format| decode(path, format))
+    }
+}
+
+/// Figures out if the file is an animation from its contents, still images return `None`
+pub fn detect(path: &str) -> Option<AnimationFormat> {
+    let bytes = fs::read(path).expect("Could not open image");
+    if bytes.starts_with(b"GIF8") {
+        Some(AnimationFormat::Gif)
+    } else if bytes.starts_with(b"\x89PNG") {
+        let decoder = PngDecoder::new(Cursor::new(&bytes)).expect("Error decoding png");
+        decoder
+            .is_apng()
+            .expect("Error decoding png")
+            .then_some(AnimationFormat::Apng)
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        let decoder = WebPDecoder::new(Cursor::new(&bytes)).expect("Error decoding webp");
+        decoder.has_animation().then_some(AnimationFormat::WebP)
+    } else {
+        None
+    }
+}
+
+pub fn decode(path: &str, format: AnimationFormat) -> Animation {
+    let bytes = fs::read(path).expect("Could not open image");
+    let (frames, loop_count) = match format {
+        AnimationFormat::Gif => (
+            GifDecoder::new(Cursor::new(&bytes))
+                .unwrap()
+                .into_frames()
+                .collect_frames()
+                .expect("Error decoding gif"),
+            gif_loop_count(&bytes),
+        ),
+        AnimationFormat::Apng => (
+            PngDecoder::new(Cursor::new(&bytes))
+                .unwrap()
+                .apng()
+                .unwrap()
+                .into_frames()
+                .collect_frames()
+                .expect("Error decoding apng"),
+            apng_loop_count(&bytes),
+        ),
+        AnimationFormat::WebP => (
+            WebPDecoder::new(Cursor::new(&bytes))
+                .unwrap()
+                .into_frames()
+                .collect_frames()
+                .expect("Error decoding webp"),
+            webp_loop_count(&bytes),
+        ),
+    };
+    Animation { frames, loop_count }
+}
+
+/// Finds the frames of an image sequence given as a directory or glob pattern, sorted by the
+/// number in their file names
+fn sequence(path: &str) -> Option<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = if Path::new(path).is_dir() {
+        fs::read_dir(path)
+            .expect("Could not read frame directory")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| image::ImageFormat::from_path(path).is_ok())
+            .collect()
+    } else if path.contains(['*', '?', '[']) {
+        glob::glob(path)
+            .expect("Invalid glob pattern")
+            .map(|path| path.unwrap())
+            .collect()
+    } else {
+        return None;
+    };
+    if paths.is_empty() {
+        panic!("No frames found in {path}");
+    }
+    paths.sort_by_key(|path| {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let digits: String = name
+            .chars()
+            .rev()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        let number = digits.chars().rev().collect::<String>().parse::<u64>().ok();
+        (number, name.into_owned())
+    });
+    Some(paths)
+}
+
+fn decode_sequence(paths: &[PathBuf], fps: f32) -> Animation {
+    let delay = Delay::from_saturating_duration(Duration::from_secs_f32(1. / fps));
+    let frames = paths
+        .iter()
+        .map(|path| {
+            let img = image::open(path)
+                .unwrap_or_else(|_| panic!("Could not open frame {}", path.display()));
+            Frame::from_parts(img.into(), 0, 0, delay)
+        })
+        .collect();
+    Animation {
+        frames,
+        loop_count: LoopCount::Infinite,
+    }
+}
+
+/// Saves the frames as an animation, or as numbered images if the path has a run of `#` in it
+/// (`frames/out-###.png` becomes `frames/out-001.png`, `frames/out-002.png`, ...)
+pub fn encode(path: &str, frames: Vec<Frame>, loop_count: LoopCount) {
+    if path.contains('#') {
+        encode_sequence(path, frames);
+        return;
+    }
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("gif") => {
+            let mut encoder = GifEncoder::new(File::create(path).unwrap());
+            match loop_count {
+                LoopCount::Infinite => encoder.set_repeat(Repeat::Infinite).unwrap(),
+                LoopCount::Plays(1) => {}
+                LoopCount::Plays(plays) => encoder
+                    .set_repeat(Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16))
+                    .unwrap(),
+            }
+            encoder.encode_frames(frames).unwrap();
+        }
+        Some("png") | Some("apng") => encode_apng(path, frames, loop_count),
+        Some("webp") => encode_webp(path, frames, loop_count),
+        _ => panic!("Unknown animation format for {path}, use .gif, .png or .webp"),
+    }
+}
+
+fn encode_sequence(path: &str, frames: Vec<Frame>) {
+    let width = path.chars().filter(|c| *c == '#').count();
+    let start = path.find('#').unwrap();
+    for (index, frame) in frames.into_iter().enumerate() {
+        let frame_path = format!(
+            "{}{:0width$}{}",
+            &path[..start],
+            index + 1,
+            &path[start + width..]
+        );
+        frame
+            .into_buffer()
+            .save(&frame_path)
+            .unwrap_or_else(|_| panic!("Could not save frame {frame_path}"));
+    }
+}
+
+fn encode_apng(path: &str, frames: Vec<Frame>, loop_count: LoopCount) {
+    let (width, height) = frames[0].buffer().dimensions();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let plays = match loop_count {
+        LoopCount::Infinite => 0,
+        LoopCount::Plays(plays) => plays,
+    };
+    encoder.set_animated(frames.len() as u32, plays).unwrap();
+    let mut writer = encoder.write_header().unwrap();
+    for frame in frames {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let milliseconds = (numerator / denominator.max(1)).min(u16::MAX as u32) as u16;
+        writer.set_frame_delay(milliseconds, 1000).unwrap();
+        writer.write_image_data(frame.buffer()).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+/// Writes a lossless animated webp, every frame is encoded on its own and wrapped in an ANMF
+/// chunk that replaces the whole canvas
+fn encode_webp(path: &str, frames: Vec<Frame>, loop_count: LoopCount) {
+    let (width, height) = frames[0].buffer().dimensions();
+    let plays = match loop_count {
+        LoopCount::Infinite => 0,
+        LoopCount::Plays(plays) => plays.min(u16::MAX as u32) as u16,
+    };
+    let mut vp8x = vec![0x10 | 0x02, 0, 0, 0];
+    vp8x.extend(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend(&(height - 1).to_le_bytes()[..3]);
+    let mut anim = vec![0; 4];
+    anim.extend(plays.to_le_bytes());
+    let mut body = b"WEBP".to_vec();
+    webp_chunk(&mut body, b"VP8X", &vp8x);
+    webp_chunk(&mut body, b"ANIM", &anim);
+    for frame in frames {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let milliseconds = (numerator / denominator.max(1)).min(0xff_ffff);
+        let mut image = vec![];
+        WebPEncoder::new_lossless(&mut image)
+            .encode(frame.buffer(), width, height, ExtendedColorType::Rgba8)
+            .expect("Error encoding webp");
+        let mut anmf = vec![0; 6];
+        anmf.extend(&(width - 1).to_le_bytes()[..3]);
+        anmf.extend(&(height - 1).to_le_bytes()[..3]);
+        anmf.extend(&milliseconds.to_le_bytes()[..3]);
+        // Don't blend with the previous frame and don't dispose of this one
+        anmf.push(0x02);
+        // Everything after the RIFF header of the still image is its VP8L chunk
+        anmf.extend(&image[12..]);
+        webp_chunk(&mut body, b"ANMF", &anmf);
+    }
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend((body.len() as u32).to_le_bytes());
+    bytes.extend(body);
+    fs::write(path, bytes).expect("Could not save the animation");
+}
+
+fn webp_chunk(bytes: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) {
+    bytes.extend(name);
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend(data);
+    if data.len() % 2 == 1 {
+        bytes.push(0);
+    }
+}
+
+fn gif_loop_count(bytes: &[u8]) -> LoopCount {
+    let Some(start) = bytes
+        .windows(11)
+        .position(|window| window == b"NETSCAPE2.0")
+    else {
+        return LoopCount::Plays(1);
+    };
+    match bytes.get(start + 13..start + 15) {
+        Some([0, 0]) => LoopCount::Infinite,
+        Some([low, high]) => LoopCount::Plays(u16::from_le_bytes([*low, *high]) as u32 + 1),
+        _ => LoopCount::Plays(1),
+    }
+}
+
+fn apng_loop_count(bytes: &[u8]) -> LoopCount {
+    let mut offset = 8;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        if &header[4..8] == b"acTL" {
+            return match bytes.get(offset + 12..offset + 16) {
+                Some([0, 0, 0, 0]) | None => LoopCount::Infinite,
+                Some(plays) => LoopCount::Plays(u32::from_be_bytes(plays.try_into().unwrap())),
+            };
+        }
+        offset += 12 + length;
+    }
+    LoopCount::Infinite
+}
+
+fn webp_loop_count(bytes: &[u8]) -> LoopCount {
+    let mut offset = 12;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+        if &header[0..4] == b"ANIM" {
+            return match bytes.get(offset + 12..offset + 14) {
+                Some([low, high]) if [*low, *high] != [0, 0] => {
+                    LoopCount::Plays(u16::from_le_bytes([*low, *high]) as u32)
+                }
+                _ => LoopCount::Infinite,
+            };
+        }
+        offset += 8 + length + length % 2;
+    }
+    LoopCount::Infinite
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn frames() -> Vec<Frame> {
+        [([255, 0, 0, 255], 100), ([0, 0, 255, 255], 50)]
+            .into_iter()
+            .map(|(color, delay)| {
+                Frame::from_parts(
+                    ImageBuffer::from_pixel(4, 3, Rgba(color)),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(delay, 1),
+                )
+            })
+            .collect()
+    }
+
+    /// Encodes the test frames to a temporary file and decodes them again
+    fn round_trip(name: &str, format: AnimationFormat, loop_count: LoopCount) -> Animation {
+        let path = std::env::temp_dir().join(format!("artist-{}-{name}", std::process::id()));
+        let path = path.to_str().unwrap();
+        encode(path, frames(), loop_count);
+        let animation = decode(path, format);
+        fs::remove_file(path).unwrap();
+        animation
+    }
+
+    fn assert_same_frames(animation: &Animation) {
+        assert_eq!(animation.frames.len(), 2);
+        for (decoded, original) in animation.frames.iter().zip(frames()) {
+            assert_eq!(decoded.buffer(), original.buffer());
+            assert_eq!(
+                Duration::from(decoded.delay()),
+                Duration::from(original.delay())
+            );
+        }
+    }
+
+    #[test]
+    fn frame_ranges_need_an_end_after_the_start() {
+        let range: FrameRange = "10..40".parse().unwrap();
+        assert_eq!((range.start, range.end), (10, Some(40)));
+        let range: FrameRange = "..".parse().unwrap();
+        assert_eq!((range.start, range.end), (0, None));
+        assert!("40..10".parse::<FrameRange>().is_err());
+        assert!("10..10".parse::<FrameRange>().is_err());
+        assert!("10".parse::<FrameRange>().is_err());
+    }
+
+    #[test]
+    fn gifs_keep_their_frames_and_loop_count() {
+        let animation = round_trip("loop.gif", AnimationFormat::Gif, LoopCount::Plays(3));
+        assert_same_frames(&animation);
+        assert_eq!(animation.loop_count, LoopCount::Plays(3));
+        let animation = round_trip("infinite.gif", AnimationFormat::Gif, LoopCount::Infinite);
+        assert_eq!(animation.loop_count, LoopCount::Infinite);
+        let animation = round_trip("once.gif", AnimationFormat::Gif, LoopCount::Plays(1));
+        assert_eq!(animation.loop_count, LoopCount::Plays(1));
+    }
+
+    #[test]
+    fn apngs_keep_their_frames_and_loop_count() {
+        let animation = round_trip("loop.png", AnimationFormat::Apng, LoopCount::Plays(2));
+        assert_same_frames(&animation);
+        assert_eq!(animation.loop_count, LoopCount::Plays(2));
+        let animation = round_trip("infinite.png", AnimationFormat::Apng, LoopCount::Infinite);
+        assert_eq!(animation.loop_count, LoopCount::Infinite);
+    }
+
+    #[test]
+    fn webps_keep_their_frames_and_loop_count() {
+        let animation = round_trip("loop.webp", AnimationFormat::WebP, LoopCount::Plays(4));
+        assert_same_frames(&animation);
+        assert_eq!(animation.loop_count, LoopCount::Plays(4));
+        let animation = round_trip("infinite.webp", AnimationFormat::WebP, LoopCount::Infinite);
+        assert_eq!(animation.loop_count, LoopCount::Infinite);
+    }
+
+    #[test]
+    fn loop_counts_fall_back_without_their_chunk() {
+        assert_eq!(gif_loop_count(b"GIF89a"), LoopCount::Plays(1));
+        // The extension is cut off before its loop count
+        assert_eq!(
+            gif_loop_count(b"GIF89a!\xff\x0bNETSCAPE2.0"),
+            LoopCount::Plays(1)
+        );
+        assert_eq!(apng_loop_count(b"\x89PNG\r\n\x1a\n"), LoopCount::Infinite);
+        assert_eq!(webp_loop_count(b"RIFF\x04\0\0\0WEBP"), LoopCount::Infinite);
+    }
+
+    #[test]
+    fn loop_counts_are_read_from_their_chunks() {
+        let gif = b"GIF89a!\xff\x0bNETSCAPE2.0\x03\x01\x04\x00\x00";
+        assert_eq!(gif_loop_count(gif), LoopCount::Plays(5));
+        let mut apng = b"\x89PNG\r\n\x1a\n".to_vec();
+        apng.extend(8u32.to_be_bytes());
+        apng.extend(b"acTL");
+        apng.extend(2u32.to_be_bytes());
+        apng.extend(7u32.to_be_bytes());
+        assert_eq!(apng_loop_count(&apng), LoopCount::Plays(7));
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        webp_chunk(&mut webp, b"ANIM", &[0, 0, 0, 0, 6, 0]);
+        assert_eq!(webp_loop_count(&webp), LoopCount::Plays(6));
+    }
+}
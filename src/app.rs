@@ -0,0 +1,263 @@
+use crate::backend::Key;
+use crate::paint::parse_color;
+use crate::timing::Wait;
+use image::Rgb;
+use serde::{Deserialize, Deserializer};
+
+/// The built in profiles, they are written in the same format as profiles in the config file
+const BUILT_IN: &[(&str, &str)] = &[
+    ("mspaint", include_str!("apps/mspaint.toml")),
+    ("classic-paint", include_str!("apps/classic-paint.toml")),
+    ("pinta", include_str!("apps/pinta.toml")),
+    ("kolourpaint", include_str!("apps/kolourpaint.toml")),
+    ("gimp", include_str!("apps/gimp.toml")),
+    ("canvas", include_str!("apps/canvas.toml")),
+];
+
+/// Describes how to drive a painting program, every action is a list of steps
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AppProfile {
+    /// Hex codes of the color presets row by row, starting with the top left one which the mouse
+    /// points at during calibration
+    #[serde(deserialize_with = "colors")]
+    pub presets: Vec<Rgb<u8>>,
+    /// How many presets are in a row
+    #[serde(default)]
+    pub columns: usize,
+    /// Where every preset is relative to the first one, for palettes that aren't a grid of
+    /// `columns` presets
+    #[serde(default)]
+    pub swatches: Vec<[i32; 2]>,
+    /// How many custom colors can be kept in the app
+    #[serde(default)]
+    pub custom_slots: usize,
+    /// Where the first custom color is relative to the top left color preset, the custom colors are
+    /// in rows of `custom_columns` from there. Defaults to the row below the last row of presets
+    #[serde(default)]
+    pub custom_origin: Option<[i32; 2]>,
+    /// How many custom colors are in a row, defaults to `columns`
+    #[serde(default)]
+    pub custom_columns: Option<usize>,
+    /// Where the color 1 swatch is relative to the top left color preset
+    #[serde(default)]
+    pub color_1_offset: Option<[i32; 2]>,
+    /// Opens the dialog that edits color 1, apps without one can only paint with the presets
+    #[serde(default)]
+    pub open_color_dialog: Vec<Step>,
+    /// Enters the color into the open dialog and closes it
+    #[serde(default)]
+    pub enter_color: Vec<Step>,
+    /// Makes the color dialog edit color 1 again after `select_color_2`
+    #[serde(default)]
+    pub select_color_1: Vec<Step>,
+    /// Makes the color dialog edit color 2, which fills rectangles
+    #[serde(default)]
+    pub select_color_2: Vec<Step>,
+    pub select_brush: Vec<Step>,
+    pub max_brush_size: Vec<Step>,
+    /// Removes everything from the canvas
+    pub clear_canvas: Vec<Step>,
+    /// Selects a tool that draws rectangles with color 1 and fills them with color 2
+    pub select_rectangle: Vec<Step>,
+    /// Runs after dragging out a rectangle to commit it
+    #[serde(default)]
+    pub finish_rectangle: Vec<Step>,
+}
+
+impl AppProfile {
+    pub fn has_color_dialog(&self) -> bool {
+        !self.open_color_dialog.is_empty()
+    }
+
+    /// Where the first custom color is relative to the top left color preset when the presets are
+    /// `spacing` pixels apart
+    pub fn custom_origin(&self, spacing: i32) -> [i32; 2] {
+        self.custom_origin.unwrap_or([
+            0,
+            self.presets.len().div_ceil(self.columns) as i32 * spacing,
+        ])
+    }
+
+    pub fn custom_columns(&self) -> usize {
+        self.custom_columns.unwrap_or(self.columns)
+    }
+
+    /// Replaces the presets with the colors of a palette loaded into the app, in rows of
+    /// `columns`. The custom colors keep the place and rows they had next to the old presets
+    pub fn replace_presets(&mut self, presets: Vec<Rgb<u8>>, columns: usize, spacing: i32) {
+        if self.custom_slots > 0 {
+            self.custom_origin = Some(self.custom_origin(spacing));
+            self.custom_columns = Some(self.custom_columns());
+        }
+        self.columns = columns;
+        self.presets = presets;
+        self.swatches.clear();
+    }
+
+    /// Checks that the palette can be found and the custom colors can be set
+    pub fn check(&self) -> Result<(), String> {
+        if self.presets.is_empty() {
+            return Err("There are no presets".to_string());
+        }
+        if self.swatches.is_empty() && self.columns == 0 {
+            return Err("Either columns or swatches has to be set".to_string());
+        }
+        if !self.swatches.is_empty() && self.swatches.len() != self.presets.len() {
+            return Err(format!(
+                "There are {} swatches for {} presets",
+                self.swatches.len(),
+                self.presets.len()
+            ));
+        }
+        if self.custom_slots > 0 && (self.custom_columns() == 0 || !self.has_color_dialog()) {
+            return Err("Custom slots need columns and a color dialog".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// One step of an action, written like
+/// - `alt e c` to press keys one after another, `ctrl+a` to press them together and `tab*4` to
+///   press them several times
+/// - `type #{hex}` to type text, `{hex}`, `{r}`, `{g}` and `{b}` are replaced with the color
+/// - `click canvas 2 -18` or `click palette -85 10` to click relative to the top left corner of the
+///   painting or the top left color preset
+/// - `wait small`, `wait medium` or `wait long`
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "String")]
+pub enum Step {
+    Keys(Vec<(Vec<Key>, usize)>),
+    Type(String),
+    Click(Anchor, i32, i32),
+    Wait(Wait),
+}
+
+#[derive(Clone, Copy)]
+pub enum Anchor {
+    Canvas,
+    Palette,
+}
+
+impl TryFrom<String> for Step {
+    type Error = String;
+
+    fn try_from(step: String) -> Result<Self, Self::Error> {
+        let words: Vec<&str> = step.split_whitespace().collect();
+        match words.as_slice() {
+            ["type", ..] => Ok(Step::Type(
+                step.trim_start()["type".len()..].trim().to_string(),
+            )),
+            ["wait", wait] => Ok(Step::Wait(match *wait {
+                "small" => Wait::Small,
+                "medium" => Wait::Medium,
+                "long" => Wait::Long,
+                _ => return Err(format!("Unknown wait \"{wait}\" in step \"{step}\"")),
+            })),
+            ["click", anchor, x, y] => {
+                let anchor = match *anchor {
+                    "canvas" => Anchor::Canvas,
+                    "palette" => Anchor::Palette,
+                    _ => return Err(format!("Unknown anchor \"{anchor}\" in step \"{step}\"")),
+                };
+                let parse = |number: &str| {
+                    number
+                        .parse::<i32>()
+                        .map_err(|_| format!("Invalid coordinate \"{number}\" in step \"{step}\""))
+                };
+                Ok(Step::Click(anchor, parse(x)?, parse(y)?))
+            }
+            [] => Err("Empty step".to_string()),
+            presses => presses
+                .iter()
+                .map(|press| {
+                    let (chord, times) = match press.split_once('*') {
+                        Some((chord, times)) => (
+                            chord,
+                            times
+                                .parse()
+                                .map_err(|_| format!("Invalid repeat count in step \"{step}\""))?,
+                        ),
+                        None => (*press, 1),
+                    };
+                    let keys = chord.split('+').map(parse_key).collect::<Result<_, _>>()?;
+                    Ok((keys, times))
+                })
+                .collect::<Result<_, _>>()
+                .map(Step::Keys),
+        }
+    }
+}
+
+fn parse_key(name: &str) -> Result<Key, String> {
+    let key = match name.to_ascii_lowercase().as_str() {
+        "alt" => Key::Alt,
+        "ctrl" | "control" => Key::Control,
+        "shift" => Key::Shift,
+        "meta" | "super" => Key::Meta,
+        "tab" => Key::Tab,
+        "return" | "enter" => Key::Return,
+        "escape" | "esc" => Key::Escape,
+        "delete" => Key::Delete,
+        "backspace" => Key::Backspace,
+        "capslock" => Key::CapsLock,
+        "space" => Key::Space,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Key::Layout(c.to_ascii_lowercase()),
+                _ => return Err(format!("Unknown key \"{name}\"")),
+            }
+        }
+    };
+    Ok(key)
+}
+
+fn colors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Rgb<u8>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|hex| parse_color(hex).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// A built in profile by name, or `None` if there is no profile with that name
+pub fn built_in(name: &str) -> Option<AppProfile> {
+    BUILT_IN
+        .iter()
+        .find(|(built_in, _)| *built_in == name)
+        .map(|(_, profile)| {
+            toml::from_str(profile)
+                .unwrap_or_else(|error| panic!("Invalid built in app profile {name}: {error}"))
+        })
+}
+
+/// Replaces the color placeholders in text that is typed by a step
+pub fn fill_color(text: &str, color: Rgb<u8>) -> String {
+    text.replace(
+        "{hex}",
+        &format!("{:02X?}{:02X?}{:02X?}", color.0[0], color.0[1], color.0[2]),
+    )
+    .replace("{r}", &color.0[0].to_string())
+    .replace("{g}", &color.0[1].to_string())
+    .replace("{b}", &color.0[2].to_string())
+}
@@ -0,0 +1,466 @@
+#[cfg(feature = "enigo")]
+use enigo::{KeyboardControllable, MouseButton, MouseControllable};
+use image::imageops::{crop_imm, resize, FilterType};
+use image::{ImageBuffer, Rgba};
+use std::str::FromStr;
+use xcap::Monitor;
+
+#[cfg(target_os = "linux")]
+mod uinput;
+#[cfg(target_os = "linux")]
+mod x11;
+
+/// The keys app profiles can press, every input backend maps them to its own key codes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Alt,
+    Backspace,
+    CapsLock,
+    Control,
+    Delete,
+    DownArrow,
+    End,
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Home,
+    LeftArrow,
+    Meta,
+    PageDown,
+    PageUp,
+    Return,
+    RightArrow,
+    Shift,
+    Space,
+    Tab,
+    UpArrow,
+    /// The key that types the character
+    Layout(char),
+}
+
+/// Sends mouse and keyboard input to the painting program
+pub trait Input {
+    fn move_to(&mut self, x: i32, y: i32);
+    fn button_down(&mut self);
+    fn button_up(&mut self);
+    fn key_down(&mut self, key: Key);
+    fn key_up(&mut self, key: Key);
+    /// Where the mouse is, used to notice the user moving it to stop the program
+    fn location(&self) -> (i32, i32);
+
+    fn click(&mut self) {
+        self.button_down();
+        self.button_up();
+    }
+
+    fn key_click(&mut self, key: Key) {
+        self.key_down(key);
+        self.key_up(key);
+    }
+
+    fn type_text(&mut self, text: &str) {
+        for character in text.chars() {
+            self.key_click(match character {
+                '\n' => Key::Return,
+                '\t' => Key::Tab,
+                _ => Key::Layout(character),
+            });
+        }
+    }
+}
+
+/// What the recording backend was sent
+#[cfg(test)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Move(i32, i32),
+    ButtonDown,
+    ButtonUp,
+    KeyDown(Key),
+    KeyUp(Key),
+}
+
+/// Keeps the input instead of sending it, clones share the events so a test can read what the
+/// artist it handed one to did
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Recording {
+    pub events: std::rc::Rc<std::cell::RefCell<Vec<Event>>>,
+    location: (i32, i32),
+}
+
+#[cfg(test)]
+impl Input for Recording {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.events.borrow_mut().push(Event::Move(x, y));
+        self.location = (x, y);
+    }
+
+    fn button_down(&mut self) {
+        self.events.borrow_mut().push(Event::ButtonDown);
+    }
+
+    fn button_up(&mut self) {
+        self.events.borrow_mut().push(Event::ButtonUp);
+    }
+
+    fn key_down(&mut self, key: Key) {
+        self.events.borrow_mut().push(Event::KeyDown(key));
+    }
+
+    fn key_up(&mut self, key: Key) {
+        self.events.borrow_mut().push(Event::KeyUp(key));
+    }
+
+    fn location(&self) -> (i32, i32) {
+        self.location
+    }
+}
+
+/// Takes screenshots of the painting program
+pub trait Capture {
+    /// Screenshot of the monitor that contains the point
+    fn capture(&self, x: i32, y: i32) -> Screenshot;
+}
+
+/// Where a monitor is in the coordinates the mouse uses, which are logical pixels on scaled
+/// displays
+#[derive(Clone, Copy, Debug)]
+pub struct MonitorArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorArea {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        (self.x..self.x + self.width as i32).contains(&x)
+            && (self.y..self.y + self.height as i32).contains(&y)
+    }
+}
+
+/// A screenshot in physical pixels and the monitor it shows
+pub struct Screenshot {
+    pub image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub area: MonitorArea,
+}
+
+impl Screenshot {
+    /// How many physical pixels make up a logical one
+    fn scale(&self) -> (f32, f32) {
+        (
+            self.image.width() as f32 / self.area.width as f32,
+            self.image.height() as f32 / self.area.height as f32,
+        )
+    }
+
+    /// The pixel of the image under the point, which is in mouse coordinates
+    fn to_physical(&self, x: i32, y: i32) -> (u32, u32) {
+        let (scale_x, scale_y) = self.scale();
+        (
+            (((x - self.area.x) as f32 * scale_x) as u32).min(self.image.width() - 1),
+            (((y - self.area.y) as f32 * scale_y) as u32).min(self.image.height() - 1),
+        )
+    }
+
+    /// The color under the point, which is in mouse coordinates
+    pub fn pixel(&self, x: i32, y: i32) -> Rgba<u8> {
+        let (x, y) = self.to_physical(x, y);
+        *self.image.get_pixel(x, y)
+    }
+
+    /// The rectangle at the point in mouse coordinates, scaled down to one pixel per logical pixel
+    pub fn crop(&self, x: i32, y: i32, width: u32, height: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+        if !self.area.contains(x, y) || !self.area.contains(right, bottom) {
+            panic!(
+                "The painting has to be on a single monitor, {}x{} at {}, {} goes past {:?}",
+                width, height, x, y, self.area
+            );
+        }
+        let (left, top) = self.to_physical(x, y);
+        let (scale_x, scale_y) = self.scale();
+        let physical = crop_imm(
+            &self.image,
+            left,
+            top,
+            (width as f32 * scale_x).round() as u32,
+            (height as f32 * scale_y).round() as u32,
+        )
+        .to_image();
+        if physical.dimensions() == (width, height) {
+            physical
+        } else {
+            resize(&physical, width, height, FilterType::Nearest)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum InputBackend {
+    Enigo,
+    /// The XTest extension of the X server, works without libxdo and on Xvfb
+    XTest,
+    /// A virtual mouse and keyboard made with /dev/uinput, works on Wayland
+    Uinput,
+}
+
+impl FromStr for InputBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enigo" => Ok(InputBackend::Enigo),
+            "xtest" => Ok(InputBackend::XTest),
+            "uinput" => Ok(InputBackend::Uinput),
+            _ => Err(format!(
+                "Unknown input backend \"{s}\", expected enigo, xtest or uinput"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum CaptureBackend {
+    Xcap,
+    /// GetImage on the root window of the X server, works on Xvfb
+    X11,
+}
+
+impl FromStr for CaptureBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xcap" => Ok(CaptureBackend::Xcap),
+            "x11" => Ok(CaptureBackend::X11),
+            _ => Err(format!(
+                "Unknown capture backend \"{s}\", expected xcap or x11"
+            )),
+        }
+    }
+}
+
+pub fn input(backend: InputBackend) -> Box<dyn Input> {
+    match backend {
+        #[cfg(feature = "enigo")]
+        InputBackend::Enigo => Box::new(enigo::Enigo::new()),
+        #[cfg(not(feature = "enigo"))]
+        InputBackend::Enigo => {
+            panic!("This build leaves out the enigo input backend, use --input xtest or uinput")
+        }
+        #[cfg(target_os = "linux")]
+        InputBackend::XTest => Box::new(x11::XTest::new()),
+        #[cfg(target_os = "linux")]
+        InputBackend::Uinput => Box::new(uinput::Uinput::new()),
+        #[cfg(not(target_os = "linux"))]
+        _ => panic!("The xtest and uinput input backends are only available on Linux"),
+    }
+}
+
+pub fn capture(backend: CaptureBackend) -> Box<dyn Capture> {
+    match backend {
+        CaptureBackend::Xcap => Box::new(Xcap),
+        #[cfg(target_os = "linux")]
+        CaptureBackend::X11 => Box::new(x11::GetImage::new()),
+        #[cfg(not(target_os = "linux"))]
+        CaptureBackend::X11 => panic!("The x11 capture backend is only available on Linux"),
+    }
+}
+
+#[cfg(feature = "enigo")]
+impl Input for enigo::Enigo {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.mouse_move_to(x, y);
+    }
+
+    fn button_down(&mut self) {
+        self.mouse_down(MouseButton::Left);
+    }
+
+    fn button_up(&mut self) {
+        self.mouse_up(MouseButton::Left);
+    }
+
+    fn key_down(&mut self, key: Key) {
+        KeyboardControllable::key_down(self, enigo_key(key));
+    }
+
+    fn key_up(&mut self, key: Key) {
+        KeyboardControllable::key_up(self, enigo_key(key));
+    }
+
+    fn location(&self) -> (i32, i32) {
+        self.mouse_location()
+    }
+
+    fn click(&mut self) {
+        self.mouse_click(MouseButton::Left);
+    }
+
+    fn key_click(&mut self, key: Key) {
+        KeyboardControllable::key_click(self, enigo_key(key));
+    }
+
+    fn type_text(&mut self, text: &str) {
+        self.key_sequence(text);
+    }
+}
+
+#[cfg(feature = "enigo")]
+fn enigo_key(key: Key) -> enigo::Key {
+    match key {
+        Key::Alt => enigo::Key::Alt,
+        Key::Backspace => enigo::Key::Backspace,
+        Key::CapsLock => enigo::Key::CapsLock,
+        Key::Control => enigo::Key::Control,
+        Key::Delete => enigo::Key::Delete,
+        Key::DownArrow => enigo::Key::DownArrow,
+        Key::End => enigo::Key::End,
+        Key::Escape => enigo::Key::Escape,
+        Key::F1 => enigo::Key::F1,
+        Key::F2 => enigo::Key::F2,
+        Key::F3 => enigo::Key::F3,
+        Key::F4 => enigo::Key::F4,
+        Key::F5 => enigo::Key::F5,
+        Key::F6 => enigo::Key::F6,
+        Key::F7 => enigo::Key::F7,
+        Key::F8 => enigo::Key::F8,
+        Key::F9 => enigo::Key::F9,
+        Key::F10 => enigo::Key::F10,
+        Key::F11 => enigo::Key::F11,
+        Key::F12 => enigo::Key::F12,
+        Key::Home => enigo::Key::Home,
+        Key::LeftArrow => enigo::Key::LeftArrow,
+        Key::Meta => enigo::Key::Meta,
+        Key::PageDown => enigo::Key::PageDown,
+        Key::PageUp => enigo::Key::PageUp,
+        Key::Return => enigo::Key::Return,
+        Key::RightArrow => enigo::Key::RightArrow,
+        Key::Shift => enigo::Key::Shift,
+        Key::Space => enigo::Key::Space,
+        Key::Tab => enigo::Key::Tab,
+        Key::UpArrow => enigo::Key::UpArrow,
+        Key::Layout(character) => enigo::Key::Layout(character),
+    }
+}
+
+/// Every monitor in mouse coordinates, the first one is the fallback for points outside of all
+/// of them
+pub fn monitors() -> Vec<(Monitor, MonitorArea)> {
+    let mut monitors: Vec<_> = Monitor::all()
+        .expect("Could not list the monitors")
+        .into_iter()
+        .map(|monitor| {
+            let area = monitor_area(&monitor);
+            (monitor, area)
+        })
+        .collect();
+    monitors.sort_by_key(|(monitor, _)| !monitor.is_primary());
+    monitors
+}
+
+/// xcap divides by the scale factor on X11, where the mouse moves in physical pixels, and gives
+/// physical pixels on Windows, where this program isn't DPI aware and gets logical ones. macOS
+/// uses points everywhere
+fn monitor_area(monitor: &Monitor) -> MonitorArea {
+    #[cfg(target_os = "linux")]
+    let factor = monitor.scale_factor();
+    #[cfg(target_os = "windows")]
+    let factor = 1. / monitor.scale_factor();
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    let factor = 1.;
+    let scale = |value: i32| (value as f32 * factor).round() as i32;
+    MonitorArea {
+        x: scale(monitor.x()),
+        y: scale(monitor.y()),
+        width: scale(monitor.width() as i32) as u32,
+        height: scale(monitor.height() as i32) as u32,
+    }
+}
+
+struct Xcap;
+
+impl Capture for Xcap {
+    fn capture(&self, x: i32, y: i32) -> Screenshot {
+        let mut monitors = monitors();
+        let found = monitors
+            .iter()
+            .position(|(_, area)| area.contains(x, y))
+            .unwrap_or(0);
+        let (monitor, area) = monitors.swap_remove(found);
+        Screenshot {
+            image: monitor
+                .capture_image()
+                .expect("Could not take a screenshot"),
+            area,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A monitor left of and above the primary one, every logical pixel is a square of `scale`
+    /// physical pixels colored with its logical position
+    fn screenshot(scale: u32) -> Screenshot {
+        let area = MonitorArea {
+            x: -100,
+            y: -50,
+            width: 100,
+            height: 50,
+        };
+        let image = ImageBuffer::from_fn(area.width * scale, area.height * scale, |x, y| {
+            Rgba([(x / scale) as u8, (y / scale) as u8, 0, 255])
+        });
+        Screenshot { image, area }
+    }
+
+    #[test]
+    fn points_left_of_the_primary_monitor_map_to_their_pixel() {
+        let screenshot = screenshot(1);
+        assert_eq!(screenshot.scale(), (1., 1.));
+        assert_eq!(screenshot.to_physical(-100, -50), (0, 0));
+        assert_eq!(screenshot.to_physical(-1, -1), (99, 49));
+        assert_eq!(screenshot.pixel(-60, -20), Rgba([40, 30, 0, 255]));
+    }
+
+    #[test]
+    fn scaled_monitors_map_logical_points_to_physical_pixels() {
+        let screenshot = screenshot(2);
+        assert_eq!(screenshot.scale(), (2., 2.));
+        assert_eq!(screenshot.to_physical(-100, -50), (0, 0));
+        assert_eq!(screenshot.to_physical(-50, -25), (100, 50));
+        assert_eq!(screenshot.to_physical(-1, -1), (198, 98));
+        assert_eq!(screenshot.pixel(-60, -20), Rgba([40, 30, 0, 255]));
+    }
+
+    #[test]
+    fn crops_have_one_pixel_per_logical_pixel() {
+        for scale in [1, 2, 3] {
+            let crop = screenshot(scale).crop(-90, -40, 10, 5);
+            assert_eq!(crop.dimensions(), (10, 5));
+            for (x, y, pixel) in crop.enumerate_pixels() {
+                assert_eq!(*pixel, Rgba([10 + x as u8, 10 + y as u8, 0, 255]));
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "single monitor")]
+    fn crops_past_the_monitor_are_refused() {
+        screenshot(2).crop(-5, -40, 10, 5);
+    }
+}
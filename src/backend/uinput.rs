@@ -0,0 +1,223 @@
+use super::{monitors, Input, Key};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::thread::sleep;
+use std::time::Duration;
+use std::{mem, slice};
+
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_DEV_SETUP: libc::c_ulong = 0x405c5503;
+const UI_ABS_SETUP: libc::c_ulong = 0x401c5504;
+const UI_SET_EVBIT: libc::c_ulong = 0x40045564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x40045565;
+const UI_SET_ABSBIT: libc::c_ulong = 0x40045567;
+const EV_SYN: u16 = 0;
+const EV_KEY: u16 = 1;
+const EV_ABS: u16 = 3;
+const ABS_X: u16 = 0;
+const ABS_Y: u16 = 1;
+const BTN_LEFT: u16 = 0x110;
+const KEY_LEFTSHIFT: u16 = 42;
+/// The highest key code of a regular keyboard
+const KEY_MAX: u16 = 248;
+/// How long the compositor gets to pick up the new device before it is used
+const SETUP_TIME: Duration = Duration::from_secs(1);
+
+/// A virtual absolute mouse and keyboard, characters are typed as if the keyboard had a US layout.
+/// The program can't see where the real mouse is, so moving it doesn't stop the painting
+pub struct Uinput {
+    device: File,
+    location: (i32, i32),
+}
+
+impl Uinput {
+    pub fn new() -> Self {
+        let device = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")
+            .expect("Could not open /dev/uinput, is your user allowed to write to it?");
+        let fd = device.as_raw_fd();
+        let (right, bottom) = monitors()
+            .iter()
+            .map(|(_, area)| (area.x + area.width as i32, area.y + area.height as i32))
+            .fold((1, 1), |(right, bottom), (x, y)| {
+                (right.max(x), bottom.max(y))
+            });
+        let mut setup: libc::uinput_setup = unsafe { mem::zeroed() };
+        setup.id.bustype = 0x06; // BUS_VIRTUAL
+        for (name, character) in setup.name.iter_mut().zip(b"artist") {
+            *name = *character as libc::c_char;
+        }
+        unsafe {
+            ioctl(fd, UI_SET_EVBIT, EV_KEY as libc::c_ulong);
+            ioctl(fd, UI_SET_EVBIT, EV_ABS as libc::c_ulong);
+            ioctl(fd, UI_SET_KEYBIT, BTN_LEFT as libc::c_ulong);
+            for code in 1..=KEY_MAX {
+                ioctl(fd, UI_SET_KEYBIT, code as libc::c_ulong);
+            }
+            for (code, maximum) in [(ABS_X, right - 1), (ABS_Y, bottom - 1)] {
+                ioctl(fd, UI_SET_ABSBIT, code as libc::c_ulong);
+                let mut axis: libc::uinput_abs_setup = mem::zeroed();
+                axis.code = code;
+                axis.absinfo.maximum = maximum;
+                ioctl(fd, UI_ABS_SETUP, &axis as *const _ as libc::c_ulong);
+            }
+            ioctl(fd, UI_DEV_SETUP, &setup as *const _ as libc::c_ulong);
+            ioctl(fd, UI_DEV_CREATE, 0);
+        }
+        sleep(SETUP_TIME);
+        Self {
+            device,
+            location: (0, 0),
+        }
+    }
+
+    fn emit(&mut self, kind: u16, code: u16, value: i32) {
+        let event = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_: kind,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                &event as *const _ as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.device
+            .write_all(bytes)
+            .expect("Could not send input to /dev/uinput");
+    }
+
+    fn sync(&mut self) {
+        self.emit(EV_SYN, 0, 0);
+    }
+
+    fn key(&mut self, key: Key, pressed: bool) {
+        let (code, shift) = code(key);
+        if shift && pressed {
+            self.emit(EV_KEY, KEY_LEFTSHIFT, 1);
+        }
+        self.emit(EV_KEY, code, pressed as i32);
+        if shift && !pressed {
+            self.emit(EV_KEY, KEY_LEFTSHIFT, 0);
+        }
+        self.sync();
+    }
+}
+
+impl Drop for Uinput {
+    fn drop(&mut self) {
+        unsafe {
+            ioctl(self.device.as_raw_fd(), UI_DEV_DESTROY, 0);
+        }
+    }
+}
+
+impl Input for Uinput {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.emit(EV_ABS, ABS_X, x);
+        self.emit(EV_ABS, ABS_Y, y);
+        self.sync();
+        self.location = (x, y);
+    }
+
+    fn button_down(&mut self) {
+        self.emit(EV_KEY, BTN_LEFT, 1);
+        self.sync();
+    }
+
+    fn button_up(&mut self) {
+        self.emit(EV_KEY, BTN_LEFT, 0);
+        self.sync();
+    }
+
+    fn key_down(&mut self, key: Key) {
+        self.key(key, true);
+    }
+
+    fn key_up(&mut self, key: Key) {
+        self.key(key, false);
+    }
+
+    fn location(&self) -> (i32, i32) {
+        self.location
+    }
+}
+
+unsafe fn ioctl(fd: libc::c_int, request: libc::c_ulong, argument: libc::c_ulong) {
+    if libc::ioctl(fd, request as _, argument) < 0 {
+        panic!(
+            "Could not set up the uinput device: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// The evdev key code of the key and whether shift has to be held for it
+fn code(key: Key) -> (u16, bool) {
+    let code = match key {
+        Key::Alt => 56,
+        Key::Backspace => 14,
+        Key::CapsLock => 58,
+        Key::Control => 29,
+        Key::Delete => 111,
+        Key::DownArrow => 108,
+        Key::End => 107,
+        Key::Escape => 1,
+        Key::F1 => 59,
+        Key::F2 => 60,
+        Key::F3 => 61,
+        Key::F4 => 62,
+        Key::F5 => 63,
+        Key::F6 => 64,
+        Key::F7 => 65,
+        Key::F8 => 66,
+        Key::F9 => 67,
+        Key::F10 => 68,
+        Key::F11 => 87,
+        Key::F12 => 88,
+        Key::Home => 102,
+        Key::LeftArrow => 105,
+        Key::Meta => 125,
+        Key::PageDown => 109,
+        Key::PageUp => 104,
+        Key::Return => 28,
+        Key::RightArrow => 106,
+        Key::Shift => KEY_LEFTSHIFT,
+        Key::Space => 57,
+        Key::Tab => 15,
+        Key::UpArrow => 103,
+        Key::Layout(character) => return character_code(character),
+    };
+    (code, false)
+}
+
+fn character_code(character: char) -> (u16, bool) {
+    const ROWS: [(&str, &str, u16); 4] = [
+        ("1234567890-=", "!@#$%^&*()_+", 2),
+        ("qwertyuiop[]", "QWERTYUIOP{}", 16),
+        ("asdfghjkl;'`", "ASDFGHJKL:\"~", 30),
+        ("\\zxcvbnm,./", "|ZXCVBNM<>?", 43),
+    ];
+    if character == ' ' {
+        return (57, false);
+    }
+    for (plain, shifted, first) in ROWS {
+        if let Some(i) = plain.chars().position(|found| found == character) {
+            return (first + i as u16, false);
+        }
+        if let Some(i) = shifted.chars().position(|found| found == character) {
+            return (first + i as u16, true);
+        }
+    }
+    panic!("The uinput input backend can't type \"{character}\"");
+}
@@ -0,0 +1,267 @@
+use super::{Capture, Input, Key, MonitorArea, Screenshot};
+use image::ImageBuffer;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, ImageFormat, ImageOrder, Keycode, Keysym, Screen, Window,
+    BUTTON_PRESS_EVENT, BUTTON_RELEASE_EVENT, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+    MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+const SHIFT: Keysym = 0xffe1;
+
+/// Fakes input with the XTest extension, characters that are not on the keyboard are typed by
+/// mapping them to an unused keycode like xdotool does
+pub struct XTest {
+    connection: RustConnection,
+    root: Window,
+    min_keycode: Keycode,
+    keysyms_per_keycode: u8,
+    /// The keysyms of every keycode starting at `min_keycode`
+    keysyms: Vec<Keysym>,
+    /// Keycodes that were mapped to type characters with the keysyms they had before, put back
+    /// when the backend is dropped
+    remapped: Vec<(Keycode, Vec<Keysym>)>,
+}
+
+impl XTest {
+    pub fn new() -> Self {
+        let (connection, screen) = connect();
+        if connection
+            .extension_information(x11rb::protocol::xtest::X11_EXTENSION_NAME)
+            .unwrap()
+            .is_none()
+        {
+            panic!("The X server doesn't support the XTest extension");
+        }
+        let root = connection.setup().roots[screen].root;
+        let min_keycode = connection.setup().min_keycode;
+        let count = connection.setup().max_keycode - min_keycode + 1;
+        let mapping = connection
+            .get_keyboard_mapping(min_keycode, count)
+            .unwrap()
+            .reply()
+            .expect("Could not read the keyboard mapping");
+        Self {
+            connection,
+            root,
+            min_keycode,
+            keysyms_per_keycode: mapping.keysyms_per_keycode,
+            keysyms: mapping.keysyms,
+            remapped: vec![],
+        }
+    }
+
+    fn fake(&self, kind: u8, detail: u8, x: i16, y: i16) {
+        self.connection
+            .xtest_fake_input(kind, detail, 0, self.root, x, y, 0)
+            .unwrap();
+        self.connection.flush().unwrap();
+    }
+
+    /// The keycode that types the keysym and whether shift has to be held for it
+    fn keycode(&mut self, keysym: Keysym) -> (Keycode, bool) {
+        let per_keycode = self.keysyms_per_keycode as usize;
+        let found = self
+            .keysyms
+            .iter()
+            .enumerate()
+            .find(|(i, found)| **found == keysym && i % per_keycode < 2);
+        if let Some((i, _)) = found {
+            let keycode = self.min_keycode + (i / per_keycode) as u8;
+            return (keycode, i % per_keycode == 1);
+        }
+        let spare = self
+            .keysyms
+            .chunks(per_keycode)
+            .rposition(|keysyms| keysyms.iter().all(|keysym| *keysym == 0))
+            .expect("No unused keycode to type a character that is not on the keyboard");
+        let keycode = self.min_keycode + spare as u8;
+        let keysyms = vec![keysym; per_keycode];
+        let mapped = &mut self.keysyms[spare * per_keycode..(spare + 1) * per_keycode];
+        self.remapped.push((keycode, mapped.to_vec()));
+        mapped.copy_from_slice(&keysyms);
+        self.connection
+            .change_keyboard_mapping(1, keycode, self.keysyms_per_keycode, &keysyms)
+            .unwrap();
+        self.connection.sync().unwrap();
+        (keycode, false)
+    }
+}
+
+impl Drop for XTest {
+    fn drop(&mut self) {
+        for (keycode, keysyms) in self.remapped.drain(..) {
+            // The program is exiting anyway, a failure only leaves the character mapped
+            let _ = self.connection.change_keyboard_mapping(
+                1,
+                keycode,
+                self.keysyms_per_keycode,
+                &keysyms,
+            );
+        }
+        let _ = self.connection.sync();
+    }
+}
+
+impl Input for XTest {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.fake(MOTION_NOTIFY_EVENT, 0, x as i16, y as i16);
+    }
+
+    fn button_down(&mut self) {
+        self.fake(BUTTON_PRESS_EVENT, 1, 0, 0);
+    }
+
+    fn button_up(&mut self) {
+        self.fake(BUTTON_RELEASE_EVENT, 1, 0, 0);
+    }
+
+    fn key_down(&mut self, key: Key) {
+        let (keycode, shift) = self.keycode(keysym(key));
+        if shift {
+            let (shift, _) = self.keycode(SHIFT);
+            self.fake(KEY_PRESS_EVENT, shift, 0, 0);
+        }
+        self.fake(KEY_PRESS_EVENT, keycode, 0, 0);
+    }
+
+    fn key_up(&mut self, key: Key) {
+        let (keycode, shift) = self.keycode(keysym(key));
+        self.fake(KEY_RELEASE_EVENT, keycode, 0, 0);
+        if shift {
+            let (shift, _) = self.keycode(SHIFT);
+            self.fake(KEY_RELEASE_EVENT, shift, 0, 0);
+        }
+    }
+
+    fn location(&self) -> (i32, i32) {
+        let pointer = self
+            .connection
+            .query_pointer(self.root)
+            .unwrap()
+            .reply()
+            .unwrap();
+        (pointer.root_x as i32, pointer.root_y as i32)
+    }
+}
+
+/// Takes screenshots with GetImage on the root window, which covers every monitor
+pub struct GetImage {
+    connection: RustConnection,
+    screen: usize,
+}
+
+impl GetImage {
+    pub fn new() -> Self {
+        let (connection, screen) = connect();
+        Self { connection, screen }
+    }
+}
+
+impl Capture for GetImage {
+    fn capture(&self, _x: i32, _y: i32) -> Screenshot {
+        let setup = self.connection.setup();
+        let screen = &setup.roots[self.screen];
+        let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+        let image = self
+            .connection
+            .get_image(ImageFormat::Z_PIXMAP, screen.root, 0, 0, width, height, !0)
+            .unwrap()
+            .reply()
+            .expect("Could not take a screenshot");
+        let bits_per_pixel = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == image.depth)
+            .map(|format| format.bits_per_pixel);
+        if bits_per_pixel != Some(32) {
+            panic!(
+                "Screenshots of a {} bit screen are not supported",
+                image.depth
+            );
+        }
+        let [red, green, blue] = color_masks(screen, image.visual);
+        let channel = |pixel: u32, mask: u32| ((pixel & mask) >> mask.trailing_zeros()) as u8;
+        let pixels = image.data.chunks_exact(4).flat_map(|bytes| {
+            let bytes = bytes.try_into().unwrap();
+            let pixel = match setup.image_byte_order {
+                ImageOrder::MSB_FIRST => u32::from_be_bytes(bytes),
+                _ => u32::from_le_bytes(bytes),
+            };
+            [
+                channel(pixel, red),
+                channel(pixel, green),
+                channel(pixel, blue),
+                255,
+            ]
+        });
+        Screenshot {
+            image: ImageBuffer::from_vec(width as u32, height as u32, pixels.collect()).unwrap(),
+            // X11 doesn't scale, the mouse and the root window both use physical pixels
+            area: MonitorArea {
+                x: 0,
+                y: 0,
+                width: width as u32,
+                height: height as u32,
+            },
+        }
+    }
+}
+
+fn connect() -> (RustConnection, usize) {
+    x11rb::connect(None).expect("Could not connect to the X server, is DISPLAY set?")
+}
+
+fn color_masks(screen: &Screen, visual: u32) -> [u32; 3] {
+    screen
+        .allowed_depths
+        .iter()
+        .flat_map(|depth| &depth.visuals)
+        .find(|found| found.visual_id == visual)
+        .map(|found| [found.red_mask, found.green_mask, found.blue_mask])
+        .unwrap_or([0xff0000, 0xff00, 0xff])
+}
+
+fn keysym(key: Key) -> Keysym {
+    match key {
+        Key::Alt => 0xffe9,
+        Key::Backspace => 0xff08,
+        Key::CapsLock => 0xffe5,
+        Key::Control => 0xffe3,
+        Key::Delete => 0xffff,
+        Key::DownArrow => 0xff54,
+        Key::End => 0xff57,
+        Key::Escape => 0xff1b,
+        Key::F1 => 0xffbe,
+        Key::F2 => 0xffbf,
+        Key::F3 => 0xffc0,
+        Key::F4 => 0xffc1,
+        Key::F5 => 0xffc2,
+        Key::F6 => 0xffc3,
+        Key::F7 => 0xffc4,
+        Key::F8 => 0xffc5,
+        Key::F9 => 0xffc6,
+        Key::F10 => 0xffc7,
+        Key::F11 => 0xffc8,
+        Key::F12 => 0xffc9,
+        Key::Home => 0xff50,
+        Key::LeftArrow => 0xff51,
+        Key::Meta => 0xffeb,
+        Key::PageDown => 0xff56,
+        Key::PageUp => 0xff55,
+        Key::Return => 0xff0d,
+        Key::RightArrow => 0xff53,
+        Key::Shift => SHIFT,
+        Key::Space => 0x20,
+        Key::Tab => 0xff09,
+        Key::UpArrow => 0xff52,
+        // Latin-1 characters have the same keysym as their code point, the rest are offset
+        Key::Layout(character) => match character as u32 {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code,
+            code => 0x0100_0000 + code,
+        },
+    }
+}
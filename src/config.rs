@@ -0,0 +1,196 @@
+use crate::app::{self, AppProfile};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+use toml_edit::{value, DocumentMut, Item, Table};
+
+const DEFAULT_PATH: &str = "artist.toml";
+
+/// Painting settings from the config file, anything left out falls back to the next layer (profile,
+/// shared settings, then the built in defaults) and command line flags override all of them
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub tolerance: Option<f32>,
+    pub limit: Option<usize>,
+    pub background: Option<String>,
+    pub matte: Option<String>,
+    pub verify: Option<bool>,
+    pub exact: Option<bool>,
+    pub max_passes: Option<usize>,
+    pub verify_threshold: Option<usize>,
+    pub verify_tolerance: Option<f32>,
+    pub report: Option<bool>,
+    pub max_time: Option<f32>,
+    pub max_strokes: Option<usize>,
+    pub target_quality: Option<f32>,
+    pub search_limit: Option<bool>,
+    pub edge_threshold: Option<f32>,
+    pub small_sleep_ms: Option<u64>,
+    pub medium_sleep_ms: Option<u64>,
+    pub long_sleep_ms: Option<u64>,
+    pub adaptive_timing: Option<bool>,
+    pub confirm_colors: Option<bool>,
+    /// Where the color 1 swatch is relative to the black color preset, in pixels
+    pub color_1_offset: Option<[i32; 2]>,
+    pub dot_width: Option<i32>,
+    pub color_spacing: Option<i32>,
+    /// Palette file whose colors the painting is limited to
+    pub palette: Option<String>,
+    /// Palette file with the colors of the app's swatches
+    pub app_palette: Option<String>,
+    pub palette_columns: Option<usize>,
+    /// Where the first swatch of the app palette is relative to the point picked during
+    /// calibration
+    pub palette_origin: Option<[i32; 2]>,
+    /// Name of the app profile that describes how to drive the painting program
+    pub app: Option<String>,
+    pub input: Option<String>,
+    pub capture: Option<String>,
+    pub drag_spacing: Option<f32>,
+    pub drag_speed: Option<f32>,
+    pub drag_easing: Option<String>,
+}
+
+impl Settings {
+    /// Fills the settings missing from `self` with the ones from `fallback`
+    fn or(self, fallback: Settings) -> Settings {
+        Settings {
+            tolerance: self.tolerance.or(fallback.tolerance),
+            limit: self.limit.or(fallback.limit),
+            background: self.background.or(fallback.background),
+            matte: self.matte.or(fallback.matte),
+            verify: self.verify.or(fallback.verify),
+            exact: self.exact.or(fallback.exact),
+            max_passes: self.max_passes.or(fallback.max_passes),
+            verify_threshold: self.verify_threshold.or(fallback.verify_threshold),
+            verify_tolerance: self.verify_tolerance.or(fallback.verify_tolerance),
+            report: self.report.or(fallback.report),
+            max_time: self.max_time.or(fallback.max_time),
+            max_strokes: self.max_strokes.or(fallback.max_strokes),
+            target_quality: self.target_quality.or(fallback.target_quality),
+            search_limit: self.search_limit.or(fallback.search_limit),
+            edge_threshold: self.edge_threshold.or(fallback.edge_threshold),
+            small_sleep_ms: self.small_sleep_ms.or(fallback.small_sleep_ms),
+            medium_sleep_ms: self.medium_sleep_ms.or(fallback.medium_sleep_ms),
+            long_sleep_ms: self.long_sleep_ms.or(fallback.long_sleep_ms),
+            adaptive_timing: self.adaptive_timing.or(fallback.adaptive_timing),
+            confirm_colors: self.confirm_colors.or(fallback.confirm_colors),
+            color_1_offset: self.color_1_offset.or(fallback.color_1_offset),
+            dot_width: self.dot_width.or(fallback.dot_width),
+            color_spacing: self.color_spacing.or(fallback.color_spacing),
+            palette: self.palette.or(fallback.palette),
+            app_palette: self.app_palette.or(fallback.app_palette),
+            palette_columns: self.palette_columns.or(fallback.palette_columns),
+            palette_origin: self.palette_origin.or(fallback.palette_origin),
+            app: self.app.or(fallback.app),
+            input: self.input.or(fallback.input),
+            capture: self.capture.or(fallback.capture),
+            drag_spacing: self.drag_spacing.or(fallback.drag_spacing),
+            drag_speed: self.drag_speed.or(fallback.drag_speed),
+            drag_easing: self.drag_easing.or(fallback.drag_easing),
+        }
+    }
+}
+
+/// Where the painting and the black color preset are on the screen
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct Calibration {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub black_x: i32,
+    pub black_y: i32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Profile used when none is given on the command line
+    pub profile: Option<String>,
+    /// Settings shared by all profiles
+    pub settings: Settings,
+    pub profiles: HashMap<String, Settings>,
+    pub calibration: Option<Calibration>,
+    /// App profiles for painting programs without a built in one
+    pub apps: HashMap<String, AppProfile>,
+}
+
+impl Config {
+    /// Loads the config file at `path`, or `artist.toml` in the working directory if it exists
+    pub fn load(path: Option<&str>) -> Self {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).exists() => DEFAULT_PATH,
+            None => return Self::default(),
+        };
+        let text =
+            fs::read_to_string(path).unwrap_or_else(|_| panic!("Could not open config {path}"));
+        toml::from_str(&text).unwrap_or_else(|error| panic!("Invalid config {path}: {error}"))
+    }
+
+    /// The settings of the profile layered over the shared settings, profiles in the config file
+    /// replace built in profiles with the same name
+    pub fn settings(&self, profile: Option<&str>) -> Settings {
+        let Some(name) = profile.or(self.profile.as_deref()) else {
+            return self.settings.clone();
+        };
+        let profile = self
+            .profiles
+            .get(name)
+            .cloned()
+            .or_else(|| built_in_profile(name))
+            .unwrap_or_else(|| panic!("Unknown profile {name}"));
+        profile.or(self.settings.clone())
+    }
+
+    /// The app profile with the name, app profiles in the config file replace built in ones with
+    /// the same name
+    pub fn app(&self, name: &str) -> AppProfile {
+        let app = self
+            .apps
+            .get(name)
+            .cloned()
+            .or_else(|| app::built_in(name))
+            .unwrap_or_else(|| panic!("Unknown app {name}"));
+        app.check()
+            .unwrap_or_else(|error| panic!("Invalid app {name}: {error}"));
+        app
+    }
+}
+
+fn built_in_profile(name: &str) -> Option<Settings> {
+    match name {
+        "fast-draft" => Some(Settings {
+            tolerance: Some(15.),
+            limit: Some(8),
+            ..Default::default()
+        }),
+        "photo-hq" => Some(Settings {
+            tolerance: Some(2.),
+            verify: Some(true),
+            ..Default::default()
+        }),
+        _ => None,
+    }
+}
+
+/// Stores the calibration in the config file, everything else in the file is kept as it was
+pub fn save_calibration(path: Option<&str>, calibration: Calibration) {
+    let path = path.unwrap_or(DEFAULT_PATH);
+    let text = fs::read_to_string(path).unwrap_or_default();
+    let mut document: DocumentMut = text
+        .parse()
+        .unwrap_or_else(|error| panic!("Invalid config {path}: {error}"));
+    let mut table = Table::new();
+    table["left"] = value(calibration.left as i64);
+    table["top"] = value(calibration.top as i64);
+    table["right"] = value(calibration.right as i64);
+    table["bottom"] = value(calibration.bottom as i64);
+    table["black_x"] = value(calibration.black_x as i64);
+    table["black_y"] = value(calibration.black_y as i64);
+    document["calibration"] = Item::Table(table);
+    fs::write(path, document.to_string()).expect("Could not save calibration");
+    println!("Saved calibration to {path}");
+}
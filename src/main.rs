@@ -1,42 +1,484 @@
-use std::{fs::File, io::BufReader};
-use clap::Parser;
-use image::{codecs::gif::{GifDecoder, GifEncoder}, AnimationDecoder};
-use crate::paint::{Artist, GifArtist};
+use std::time::Duration;
+use clap::{Parser, Subcommand};
+use image::Rgb;
+use crate::animation::FrameRange;
+use crate::backend::{CaptureBackend, InputBackend};
+use crate::config::{Calibration, Config};
+use crate::vector::Svg;
+use crate::paint::{
+    parse_color, Artist, Background, Budget, Drag, Easing, GifArtist, PaintOptions, Sketch,
+};
+use crate::timing::Timing;
 
+mod animation;
+mod app;
+mod backend;
+mod config;
+mod output;
 mod paint;
+mod palette;
+mod report;
+mod sketch;
+mod text;
+mod timing;
+mod vector;
 
 /// A program to draw stuff in Microsoft Paint
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
-    /// Path to image or gif to paint
-    image_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// How similar colors can be
-    #[arg(short, long, default_value_t = 5.)]
-    tolerance: f32,
+    /// Path to image, svg, animation (gif, apng or animated webp), directory of frames or glob pattern of frames to paint
+    #[arg(required = true)]
+    image_path: Option<String>,
 
-    /// How many colors the program can use
-    #[arg(short, long, default_value_t = usize::MAX)]
-    limit: usize,
+    /// Frame rate of animations made from a directory or glob pattern of frames
+    #[arg(long, default_value_t = 10., value_parser = parse_positive)]
+    fps: f32,
+
+    /// Where to save the painting, the extension picks the format (defaults to out.png or out.gif for animations, a run of # saves numbered frames)
+    #[arg(short, long)]
+    output: Option<String>,
+
+    /// Add the current time to the output file names
+    #[arg(long)]
+    timestamp: bool,
+
+    /// Also save the planned dots next to the output
+    #[arg(long)]
+    save_preview: bool,
+
+    /// Also save the image at the resolution it is painted in next to the output
+    #[arg(long)]
+    save_resized: bool,
+
+    /// Also save the dots as an indexed PNG next to the output, only for still images
+    #[arg(long)]
+    save_dots: bool,
+
+    /// Save the colors the painting uses as a palette, the extension picks the format (.gpl, .ase or .hex), only for still images
+    #[arg(long)]
+    save_palette: Option<String>,
+
+    /// Don't take a screenshot of the finished painting
+    #[arg(long)]
+    no_screenshot: bool,
+
+    /// Which frames of an animation to paint, like 10..40
+    #[arg(long)]
+    frames: Option<FrameRange>,
+
+    /// Only paint every nth frame of an animation
+    #[arg(long, default_value_t = 1, value_parser = parse_every)]
+    every: usize,
+
+    /// How much faster the painted animation plays than the original
+    #[arg(long, default_value_t = 1., value_parser = parse_positive)]
+    speed: f32,
+
+    /// Config file to load settings, profiles and calibration from [default: artist.toml if it exists]
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Named profile of settings from the config file, or the built in fast-draft and photo-hq
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Painting program to drive, mspaint, classic-paint, pinta, kolourpaint, gimp or an app from the config file [default: mspaint]
+    #[arg(long)]
+    app: Option<String>,
+
+    /// Palette file (.gpl, .txt, .hex or .pal) whose colors the painting is limited to, colors that aren't presets of the app are set with its color dialog
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Palette file (.gpl, .txt, .hex or .pal) loaded into the app, its colors replace the app's presets
+    #[arg(long)]
+    app_palette: Option<String>,
+
+    /// How many swatches of the app palette are in a row [default: from the palette file or the app]
+    #[arg(long)]
+    palette_columns: Option<usize>,
+
+    /// Where the first swatch of the app palette is relative to the point picked during calibration, like 0,40 [default: 0,0]
+    #[arg(long, value_parser = parse_point)]
+    palette_origin: Option<[i32; 2]>,
+
+    /// How to send mouse and keyboard input, enigo, xtest or uinput (the last two only on Linux) [default: enigo]
+    #[arg(long)]
+    input: Option<InputBackend>,
+
+    /// How to take screenshots, xcap or x11 (only on Linux) [default: xcap]
+    #[arg(long)]
+    capture: Option<CaptureBackend>,
+
+    /// Point at the corners and black preset again even if the config file has a saved calibration
+    #[arg(long)]
+    calibrate: bool,
+
+    /// Save the calibration to the config file so later runs can skip it, needs --calibrate if the config file already has one
+    #[arg(long)]
+    save_calibration: bool,
+
+    /// How similar colors can be [default: 5]
+    #[arg(short, long)]
+    tolerance: Option<f32>,
+
+    /// How many colors the program can use [default: no limit]
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    /// Background color as a hex code, "auto" to detect it or "none" to paint over the existing canvas [default: auto]
+    #[arg(short, long)]
+    background: Option<Background>,
+
+    /// Color that transparent pixels are blended with [default: #ffffff]
+    #[arg(short, long, value_parser = parse_color)]
+    matte: Option<Rgb<u8>>,
+
+    /// Paint every color of the image exactly instead of merging similar ones, fails if the colors don't fit in the presets and custom colors
+    #[arg(long, overrides_with = "no_exact", conflicts_with_all = ["limit", "max_time", "max_strokes", "target_quality"])]
+    exact: bool,
+
+    /// Don't paint exactly even if the config file turns it on
+    #[arg(long, overrides_with = "exact")]
+    no_exact: bool,
+
+    /// Paint over the existing canvas, only touching dots that differ from the image
+    #[arg(long)]
+    overlay: bool,
+
+    /// Check the painting after it is done and repaint dots that came out wrong
+    #[arg(long, overrides_with = "no_verify")]
+    verify: bool,
+
+    /// Don't verify the painting even if the config file turns it on
+    #[arg(long, overrides_with = "verify")]
+    no_verify: bool,
+
+    /// How many correction passes verification can make [default: 3]
+    #[arg(long)]
+    max_passes: Option<usize>,
+
+    /// How many wrong dots verification accepts [default: 0]
+    #[arg(long)]
+    verify_threshold: Option<usize>,
+
+    /// How different a dot on the screen can be from the plan before verification or adaptive timing count it as wrong [default: 5]
+    #[arg(long)]
+    verify_tolerance: Option<f32>,
+
+    /// Print how close the plan and the painting are to the image and save a comparison to report.png
+    #[arg(long, overrides_with = "no_report")]
+    report: bool,
+
+    /// Don't print or save the report even if the config file turns it on
+    #[arg(long, overrides_with = "report")]
+    no_report: bool,
+
+    /// Pick the best tolerance that paints within this many seconds
+    #[arg(long)]
+    max_time: Option<f32>,
+
+    /// Pick the best tolerance that paints with at most this many strokes
+    #[arg(long)]
+    max_strokes: Option<usize>,
+
+    /// Pick the fastest tolerance whose plan reaches this PSNR in dB
+    #[arg(long)]
+    target_quality: Option<f32>,
+
+    /// Also search for the color limit when picking settings for a budget
+    #[arg(long, overrides_with = "no_search_limit")]
+    search_limit: bool,
+
+    /// Don't search for the color limit even if the config file turns it on
+    #[arg(long, overrides_with = "search_limit")]
+    no_search_limit: bool,
+
+    /// Draw the outlines of the image as lines instead of painting it with dots
+    #[arg(long)]
+    sketch: bool,
+
+    /// Paint the dots first and draw the outlines on top of them
+    #[arg(long)]
+    sketch_fill: bool,
+
+    /// Color of the outlines
+    #[arg(long, default_value = "#000000", value_parser = parse_color)]
+    sketch_color: Rgb<u8>,
+
+    /// Check with screenshots whether paint keeps up and tune how long to wait after inputs
+    #[arg(long, overrides_with = "no_adaptive_timing")]
+    adaptive_timing: bool,
+
+    /// Keep the configured waits even if the config file turns on adaptive timing
+    #[arg(long, overrides_with = "adaptive_timing")]
+    no_adaptive_timing: bool,
+
+    /// Check the screen after every step of the color dialog and the color 1 swatch after every color change, retrying steps that failed
+    #[arg(long, overrides_with = "no_confirm_colors")]
+    confirm_colors: bool,
+
+    /// Don't confirm color changes even if the config file turns it on
+    #[arg(long, overrides_with = "confirm_colors")]
+    no_confirm_colors: bool,
+
+    /// How strong an edge has to be to become part of the outlines, lower finds more edges [default: 150]
+    #[arg(long)]
+    edge_threshold: Option<f32>,
+
+    /// Move the mouse through points this many pixels apart while dragging instead of jumping to the end, 0 turns it off
+    #[arg(long)]
+    drag_spacing: Option<f32>,
+
+    /// How fast smoothed drags move in pixels per second [default: 2000]
+    #[arg(long)]
+    drag_speed: Option<f32>,
+
+    /// How smoothed drags speed up and slow down, linear or ease-in-out [default: linear]
+    #[arg(long)]
+    drag_easing: Option<Easing>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Paint text instead of an image, the painting options go before the subcommand
+    Text(TextArgs),
+}
+
+#[derive(clap::Args)]
+struct TextArgs {
+    /// The text to paint, every line is painted below the one before it
+    text: String,
+
+    /// Path to the ttf or otf font to use
+    #[arg(long)]
+    font: String,
+
+    /// Height of the text in pixels before it is scaled to the painting
+    #[arg(long, default_value_t = 40.)]
+    size: f32,
+
+    /// Color of the text
+    #[arg(long, default_value = "#000000", value_parser = parse_color)]
+    color: Rgb<u8>,
 }
 
 fn main() {
     let args = Args::parse();
-    if args.image_path.ends_with(".gif") {
-        let file_in = BufReader::new(File::open(args.image_path).unwrap());
-        let decoder = GifDecoder::new(file_in).unwrap();
-        let frames = decoder.into_frames().collect_frames().expect("Error decoding gif");
-        let mut artist = GifArtist::new(frames, args.tolerance, args.limit);
-        let new_frames = artist.paint();
-        let file_out = File::create("out.gif").unwrap();
-        let mut encoder = GifEncoder::new(file_out);
-        encoder.set_repeat(image::codecs::gif::Repeat::Infinite).unwrap();
-        encoder.encode_frames(new_frames.into_iter()).unwrap();
+    let config = Config::load(args.config.as_deref());
+    let settings = config.settings(args.profile.as_deref());
+    let color_spacing = settings.color_spacing.unwrap_or(24);
+    let allowed_colors = args
+        .palette
+        .as_deref()
+        .or(settings.palette.as_deref())
+        .map_or(vec![], |path| palette::load(path).colors);
+    let mut app = config.app(args.app.as_deref().or(settings.app.as_deref()).unwrap_or("mspaint"));
+    if let Some(path) = args.app_palette.as_deref().or(settings.app_palette.as_deref()) {
+        let palette = palette::load(path);
+        let columns = args
+            .palette_columns
+            .or(settings.palette_columns)
+            .or(palette.columns)
+            .or(Some(app.columns).filter(|columns| *columns > 0))
+            .unwrap_or(palette.colors.len());
+        app.replace_presets(palette.colors, columns, color_spacing);
+    }
+    let background = settings.background.map(|background| {
+        background
+            .parse()
+            .unwrap_or_else(|error| panic!("Invalid background in config: {error}"))
+    });
+    let matte = settings.matte.map(|matte| {
+        parse_color(&matte).unwrap_or_else(|error| panic!("Invalid matte in config: {error}"))
+    });
+    let input = settings.input.map(|input| {
+        input
+            .parse()
+            .unwrap_or_else(|error| panic!("Invalid input in config: {error}"))
+    });
+    let capture = settings.capture.map(|capture| {
+        capture
+            .parse()
+            .unwrap_or_else(|error| panic!("Invalid capture in config: {error}"))
+    });
+    let drag_easing = settings.drag_easing.map(|easing| {
+        easing
+            .parse()
+            .unwrap_or_else(|error| panic!("Invalid drag easing in config: {error}"))
+    });
+    let exact = flag(args.exact, args.no_exact).or(settings.exact).unwrap_or(false);
+    let color_limit = args.limit.or(settings.limit);
+    let budget = Budget {
+        max_time: args.max_time.or(settings.max_time).map(Duration::from_secs_f32),
+        max_strokes: args.max_strokes.or(settings.max_strokes),
+        target_quality: args.target_quality.or(settings.target_quality),
+        search_limit: flag(args.search_limit, args.no_search_limit).or(settings.search_limit).unwrap_or(false),
+    };
+    if exact && (color_limit.is_some() || budget.max_time.is_some() || budget.max_strokes.is_some() || budget.target_quality.is_some()) {
+        panic!("Exact mode can't be combined with a color limit or a budget, check limit, max_time, max_strokes and target_quality in the config");
+    }
+    let text = matches!(args.command, Some(Command::Text(_)));
+    let options = PaintOptions {
+        tolerance: args.tolerance.or(settings.tolerance).unwrap_or(5.),
+        color_limit: color_limit.unwrap_or(usize::MAX),
+        // Text is painted over the canvas as it is unless a background is asked for
+        background: args.background.or(text.then_some(Background::None)).or(background).unwrap_or(Background::Auto),
+        matte: args.matte.or(matte).unwrap_or(Rgb([255, 255, 255])),
+        overlay: args.overlay,
+        verify: flag(args.verify, args.no_verify).or(settings.verify).unwrap_or(false),
+        max_passes: args.max_passes.or(settings.max_passes).unwrap_or(3),
+        verify_threshold: args.verify_threshold.or(settings.verify_threshold).unwrap_or(0),
+        verify_tolerance: args.verify_tolerance.or(settings.verify_tolerance).unwrap_or(5.),
+        report: flag(args.report, args.no_report).or(settings.report).unwrap_or(false),
+        budget,
+        sketch: (args.sketch || args.sketch_fill).then_some(Sketch {
+            color: args.sketch_color,
+            threshold: args.edge_threshold.or(settings.edge_threshold).unwrap_or(150.),
+            fill: args.sketch_fill,
+        }),
+        timing: Timing {
+            small_sleep: Duration::from_millis(settings.small_sleep_ms.unwrap_or(5)),
+            medium_sleep: Duration::from_millis(settings.medium_sleep_ms.unwrap_or(20)),
+            long_sleep: Duration::from_millis(settings.long_sleep_ms.unwrap_or(200)),
+        },
+        adaptive_timing: flag(args.adaptive_timing, args.no_adaptive_timing).or(settings.adaptive_timing).unwrap_or(false),
+        confirm_colors: flag(args.confirm_colors, args.no_confirm_colors).or(settings.confirm_colors).unwrap_or(false),
+        color_1_offset: settings.color_1_offset.or(app.color_1_offset).map_or((-85, 10), |[x, y]| (x, y)),
+        dot_width: match settings.dot_width.unwrap_or(5) {
+            dot_width if dot_width > 0 => dot_width,
+            _ => panic!("Invalid dot width in config: it has to be at least 1"),
+        },
+        color_spacing,
+        palette_origin: args.palette_origin.or(settings.palette_origin).map_or((0, 0), |[x, y]| (x, y)),
+        input: args.input.or(input).unwrap_or(InputBackend::Enigo),
+        capture: args.capture.or(capture).unwrap_or(CaptureBackend::Xcap),
+        exact,
+        drag: args.drag_spacing.or(settings.drag_spacing).filter(|spacing| *spacing > 0.).map(|spacing| Drag {
+            spacing,
+            speed: match args.drag_speed.or(settings.drag_speed).unwrap_or(2000.) {
+                speed if speed > 0. => speed,
+                _ => panic!("The drag speed has to be more than 0"),
+            },
+            easing: args.drag_easing.or(drag_easing).unwrap_or(Easing::Linear),
+        }),
+    };
+    let image_path = args.image_path.as_deref().unwrap_or_default();
+    let animation = match args.command {
+        Some(_) => None,
+        None => animation::open(image_path, args.fps),
+    };
+    if let Some(mut animation) = animation {
+        if args.save_dots || args.save_palette.is_some() {
+            panic!("--save-dots and --save-palette only work for still images");
+        }
+        animation.select_frames(args.frames, args.every);
+        let mut artist = GifArtist::new(
+            animation.frames,
+            options,
+            calibration(&args, &config),
+            app,
+            allowed_colors,
+            args.speed,
+        );
+        let painted = artist.paint(!args.no_screenshot);
+        let path = args.output.as_deref().unwrap_or("out.gif");
+        if !args.no_screenshot {
+            let output = output::output_path(path, args.timestamp);
+            animation::encode(&output, painted.screenshots, animation.loop_count);
+        }
+        if args.save_preview {
+            let output = output::output_path(&output::with_suffix(path, "preview"), args.timestamp);
+            animation::encode(&output, painted.previews, animation.loop_count);
+        }
+        if args.save_resized {
+            let output = output::output_path(&output::with_suffix(path, "resized"), args.timestamp);
+            animation::encode(&output, painted.resized, animation.loop_count);
+        }
     } else {
-        let img = image::open(args.image_path).expect("Could not open image");
-        let mut artist = Artist::new(img.into(), args.tolerance, args.limit);
+        let svg = (args.command.is_none() && vector::is_svg(image_path)).then(|| Svg::open(image_path));
+        let img = match (&args.command, &svg) {
+            (Some(Command::Text(text)), _) => text::render(&text.text, &text.font, text.size, text.color),
+            (None, Some(svg)) => svg.fills.clone(),
+            (None, None) => image::open(image_path).expect("Could not open image").into(),
+        };
+        let mut artist = Artist::new(img, options, calibration(&args, &config), app, allowed_colors);
         artist.paint();
-        artist.screenshot().save("out.png").unwrap();
+        if let Some(svg) = &svg {
+            artist.paint_strokes(&svg.strokes, svg.width, svg.height);
+        }
+        artist.report_timing();
+        let path = args.output.as_deref().unwrap_or("out.png");
+        if !args.no_screenshot {
+            let output = output::output_path(path, args.timestamp);
+            artist.screenshot().save(output).expect("Could not save screenshot");
+        }
+        if args.save_preview {
+            let output = output::output_path(&output::with_suffix(path, "preview"), args.timestamp);
+            artist.preview().save(output).expect("Could not save preview");
+        }
+        if args.save_resized {
+            let output = output::output_path(&output::with_suffix(path, "resized"), args.timestamp);
+            artist.resized().save(output).expect("Could not save resized image");
+        }
+        if args.save_dots {
+            let output = output::output_path(&output::with_suffix(path, "dots"), args.timestamp);
+            palette::save_indexed(&output, &artist.dots(), &artist.used_colors());
+        }
+        if let Some(path) = &args.save_palette {
+            palette::save(&output::output_path(path, args.timestamp), &artist.used_colors());
+        }
+    }
+}
+
+/// Uses the calibration saved in the config file or asks for a new one
+fn calibration(args: &Args, config: &Config) -> Calibration {
+    match config.calibration {
+        Some(_) if args.save_calibration && !args.calibrate => {
+            panic!("The config file already has a calibration, add --calibrate to replace it with a new one")
+        }
+        Some(calibration) if !args.calibrate => calibration,
+        _ => {
+            let calibration = paint::calibrate();
+            if args.save_calibration {
+                config::save_calibration(args.config.as_deref(), calibration);
+            }
+            calibration
+        }
+    }
+}
+
+/// A flag with a `--no-` counterpart, `None` if neither was given so the config file decides
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
     }
-}
\ No newline at end of file
+}
+
+fn parse_point(s: &str) -> Result<[i32; 2], String> {
+    s.split_once(',')
+        .and_then(|(x, y)| Some([x.trim().parse().ok()?, y.trim().parse().ok()?]))
+        .ok_or(format!("Invalid point \"{s}\", expected something like 0,40"))
+}
+
+fn parse_every(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("has to be at least 1".to_string()),
+        Ok(every) => Ok(every),
+        Err(_) => Err(format!("\"{s}\" is not a whole number")),
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(speed) if speed > 0. && speed.is_finite() => Ok(speed),
+        Ok(_) => Err("has to be a finite number more than 0".to_string()),
+        Err(_) => Err(format!("\"{s}\" is not a number")),
+    }
+}
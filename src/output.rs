@@ -0,0 +1,58 @@
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Adds the current time to the file name if asked to and warns if an existing file is in the way
+pub fn output_path(path: &str, timestamp: bool) -> String {
+    let path = if timestamp {
+        with_suffix(path, &current_timestamp())
+    } else {
+        path.to_string()
+    };
+    if Path::new(&path).exists() {
+        println!("Overwriting {path}");
+    }
+    path
+}
+
+/// Turns `out.png` and `preview` into `out-preview.png`
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{suffix}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{suffix}"),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// The current UTC time formatted like `20240131-235959`
+fn current_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    // Converts days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -1,51 +1,289 @@
 use device_query::{DeviceQuery, DeviceState, Keycode};
-use enigo::{Key, KeyboardControllable, MouseButton, MouseControllable};
 use image::{
-    imageops::{crop_imm, resize},
+    imageops::{resize, FilterType},
     Delay, DynamicImage, Frame, GenericImageView, ImageBuffer, Pixel, Rgb, Rgba,
 };
-use std::{collections::HashMap, thread::sleep, time::Duration};
-use xcap::Monitor;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    thread::sleep,
+    time::Duration,
+};
+
+use crate::app::{self, Anchor, AppProfile, Step};
+use crate::backend::{self, Capture, CaptureBackend, Input, InputBackend, Key, Screenshot};
+use crate::config::Calibration;
+use crate::report::{self, Metrics};
+use crate::sketch;
+use crate::timing::{Timing, TimingController, Wait};
+use crate::vector::Stroke;
+
+const INPUT_TIME: Duration = Duration::from_millis(2);
+const SEARCH_STEPS: usize = 10;
+const MIN_SEARCHED_TOLERANCE: f32 = 1.;
+const MAX_SEARCHED_TOLERANCE: f32 = 28.;
+const MAX_SEARCHED_LIMIT: usize = 64;
+const STROKE_CHECK_INTERVAL: usize = 25;
+const DIALOG_ATTEMPTS: usize = 3;
+/// How many screenshots to take while waiting for a dialog to open or close
+const DIALOG_POLLS: usize = 10;
+/// Part of the screen that has to change for a dialog to count as opened
+const SCREEN_CHANGE_RATIO: f32 = 0.005;
+/// How far the color 1 swatch can be from the color it should show, much tighter than the painting
+/// tolerance because the swatch shows the color as it was entered
+const SWATCH_TOLERANCE: f32 = 2.;
+
+#[derive(Clone, Copy)]
+pub enum Background {
+    Auto,
+    Color(Rgb<u8>),
+    None,
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Background::Auto),
+            "none" => Ok(Background::None),
+            _ => Ok(Background::Color(parse_color(s)?)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PaintOptions {
+    pub tolerance: f32,
+    pub color_limit: usize,
+    pub background: Background,
+    pub matte: Rgb<u8>,
+    pub overlay: bool,
+    pub verify: bool,
+    pub max_passes: usize,
+    pub verify_threshold: usize,
+    /// How different a dot on the screen can be from its planned color before verification or
+    /// adaptive timing count it as wrong, kept apart from `tolerance` so screen noise doesn't count
+    /// in exact mode
+    pub verify_tolerance: f32,
+    pub report: bool,
+    pub budget: Budget,
+    pub sketch: Option<Sketch>,
+    pub timing: Timing,
+    pub adaptive_timing: bool,
+    pub confirm_colors: bool,
+    /// Where the color 1 swatch is relative to the black color preset
+    pub color_1_offset: (i32, i32),
+    pub dot_width: i32,
+    pub color_spacing: i32,
+    /// Where the first preset is relative to the point picked during calibration
+    pub palette_origin: (i32, i32),
+    pub input: InputBackend,
+    pub capture: CaptureBackend,
+    pub drag: Option<Drag>,
+    /// Paints every color of the image exactly and fails if they don't fit in the presets and
+    /// custom colors
+    pub exact: bool,
+}
+
+impl PaintOptions {
+    /// Exact mode samples the image so resizing doesn't blend in new colors
+    fn resize_filter(&self) -> FilterType {
+        if self.exact {
+            FilterType::Nearest
+        } else {
+            FilterType::Gaussian
+        }
+    }
+}
+
+/// Draws the edges of the image as lines in a single color, either alone or over the dots
+#[derive(Clone, Copy)]
+pub struct Sketch {
+    pub color: Rgb<u8>,
+    pub threshold: f32,
+    pub fill: bool,
+}
+
+/// Moves the mouse through evenly spaced points while dragging instead of jumping to the end, for
+/// apps that only draw between the motion events they receive
+#[derive(Clone, Copy)]
+pub struct Drag {
+    /// Pixels between two moves
+    pub spacing: f32,
+    /// Pixels per second
+    pub speed: f32,
+    pub easing: Easing,
+}
+
+#[derive(Clone, Copy)]
+pub enum Easing {
+    Linear,
+    /// Starts and ends slowly like a hand does
+    EaseInOut,
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Easing::Linear),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            _ => Err(format!(
+                "Unknown easing \"{s}\", expected linear or ease-in-out"
+            )),
+        }
+    }
+}
+
+impl Easing {
+    /// How far along the drag is after `t` of its time
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Budget {
+    pub max_time: Option<Duration>,
+    pub max_strokes: Option<usize>,
+    pub target_quality: Option<f32>,
+    pub search_limit: bool,
+}
+
+impl Budget {
+    fn is_set(&self) -> bool {
+        self.max_time.is_some() || self.max_strokes.is_some() || self.target_quality.is_some()
+    }
+
+    fn has_cost_limit(&self) -> bool {
+        self.max_time.is_some() || self.max_strokes.is_some()
+    }
+}
 
-const SMALL_SLEEP_TIME: Duration = Duration::from_millis(5);
-const MEDIUM_SLEEP_TIME: Duration = Duration::from_millis(20);
-const LONG_SLEEP_TIME: Duration = Duration::from_millis(200);
-const DOT_WIDTH_FLOAT: f32 = 5.;
-const DOT_WIDTH: i32 = 5;
-const COLOR_SPACING: i32 = 24;
+struct Palette {
+    background: Option<Rgb<u8>>,
+    custom: Vec<Rgb<u8>>,
+}
+
+struct PlanEstimate {
+    strokes: usize,
+    duration: Duration,
+    quality: f32,
+}
+
+impl PlanEstimate {
+    fn fits(&self, budget: &Budget) -> bool {
+        budget.max_time.is_none_or(|max| self.duration <= max)
+            && budget.max_strokes.is_none_or(|max| self.strokes <= max)
+    }
+
+    fn meets(&self, budget: &Budget) -> bool {
+        budget
+            .target_quality
+            .is_none_or(|target| self.quality >= target)
+    }
+}
 
 pub struct GifArtist {
     artist: Artist,
     gif: Vec<Frame>,
+    speed: f32,
+}
+
+pub struct PaintedAnimation {
+    pub screenshots: Vec<Frame>,
+    pub previews: Vec<Frame>,
+    pub resized: Vec<Frame>,
 }
 
 impl GifArtist {
-    pub fn new(gif: Vec<Frame>, tolerance: f32, color_limit: usize) -> Self {
-        let artist = Artist::new(gif[0].buffer().clone().into(), tolerance, color_limit);
-        Self { artist, gif }
+    pub fn new(
+        gif: Vec<Frame>,
+        options: PaintOptions,
+        calibration: Calibration,
+        app: AppProfile,
+        allowed_colors: Vec<Rgb<u8>>,
+        speed: f32,
+    ) -> Self {
+        let artist = Artist::new(
+            gif[0].buffer().clone(),
+            options,
+            calibration,
+            app,
+            allowed_colors,
+        );
+        Self { artist, gif, speed }
     }
 
-    pub fn paint(&mut self) -> Vec<Frame> {
-        let mut new_frames = vec![];
+    pub fn paint(&mut self, capture: bool) -> PaintedAnimation {
+        if self.artist.options.budget.is_set() {
+            let frames: Vec<_> = self.gif.iter().map(|frame| frame.buffer()).collect();
+            self.artist.search_settings(&frames);
+        }
+        self.artist
+            .plan_palette(self.gif.iter().map(|frame| frame.buffer()));
+        let mut painted = PaintedAnimation {
+            screenshots: vec![],
+            previews: vec![],
+            resized: vec![],
+        };
         let frame = &self.gif[0];
-        new_frames.push(self.paint_frame(frame.left(), frame.top(), frame.delay()));
+        self.paint_frame(
+            frame.left(),
+            frame.top(),
+            frame.delay(),
+            capture,
+            &mut painted,
+        );
         for frame_index in 1..self.gif.len() {
             let frame = &self.gif[frame_index];
             self.artist.new_image(frame.buffer().clone());
-            new_frames.push(self.paint_frame(frame.left(), frame.top(), frame.delay()));
+            self.paint_frame(
+                frame.left(),
+                frame.top(),
+                frame.delay(),
+                capture,
+                &mut painted,
+            );
         }
-        new_frames
+        self.artist.report_timing();
+        painted
     }
 
-    fn paint_frame(&mut self, left: u32, top: u32, delay: Delay) -> Frame {
+    fn paint_frame(
+        &mut self,
+        left: u32,
+        top: u32,
+        delay: Delay,
+        capture: bool,
+        painted: &mut PaintedAnimation,
+    ) {
+        let delay = Delay::from_saturating_duration(Duration::from(delay).div_f32(self.speed));
         self.artist.paint();
-        let img = self.artist.screenshot();
-        Frame::from_parts(img, left, top, delay)
+        if capture {
+            let img = self.artist.screenshot();
+            painted
+                .screenshots
+                .push(Frame::from_parts(img, left, top, delay));
+        }
+        painted
+            .previews
+            .push(Frame::from_parts(self.artist.preview(), left, top, delay));
+        painted
+            .resized
+            .push(Frame::from_parts(self.artist.resized(), left, top, delay));
     }
 }
 
 enum PaintInstruction {
     Line(i32, i32, i32, i32),
+    Stroke(Vec<(i32, i32)>),
     Color(i32),
     ColorPrecise(Rgb<u8>),
     SetMaxSize,
@@ -61,7 +299,8 @@ enum Direction {
 }
 
 pub struct Artist {
-    enigo: enigo::Enigo,
+    input: Box<dyn Input>,
+    screen: Box<dyn Capture>,
     img: DynamicImage,
     left: i32,
     top: i32,
@@ -69,124 +308,168 @@ pub struct Artist {
     black_y: i32,
     width: i32,
     height: i32,
-    colors: [Rgb<u8>; 20],
+    colors: Vec<Rgb<u8>>,
     canvas_selected: bool,
-    tolerance: f32,
-    color_limit: usize,
+    options: PaintOptions,
+    paint_count: usize,
+    painted: Option<ImageBuffer<Rgb<u8>, Vec<u8>>>,
+    /// The dots of the last painting, transparent where nothing was painted
+    planned: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    custom_slots: Vec<Rgb<u8>>,
+    background: Option<Rgb<u8>>,
+    palette: Option<Palette>,
+    current_color: Rgb<u8>,
+    timing: Option<TimingController>,
+    stroke_count: usize,
+    app: AppProfile,
+    /// The colors the image is limited to, empty if it can use any
+    allowed_colors: Vec<Rgb<u8>>,
 }
 
 impl Artist {
-    pub fn new(img: ImageBuffer<Rgba<u8>, Vec<u8>>, tolerance: f32, color_limit: usize) -> Self {
-        let colors = [
-            *Rgb::from_slice(&[0, 0, 0]),
-            *Rgb::from_slice(&[127, 127, 127]),
-            *Rgb::from_slice(&[136, 0, 21]),
-            *Rgb::from_slice(&[237, 28, 36]),
-            *Rgb::from_slice(&[255, 127, 39]),
-            *Rgb::from_slice(&[255, 242, 0]),
-            *Rgb::from_slice(&[34, 177, 76]),
-            *Rgb::from_slice(&[0, 162, 232]),
-            *Rgb::from_slice(&[63, 72, 204]),
-            *Rgb::from_slice(&[163, 73, 164]),
-            *Rgb::from_slice(&[255, 255, 255]),
-            *Rgb::from_slice(&[195, 195, 195]),
-            *Rgb::from_slice(&[185, 122, 87]),
-            *Rgb::from_slice(&[255, 174, 201]),
-            *Rgb::from_slice(&[255, 201, 14]),
-            *Rgb::from_slice(&[239, 228, 176]),
-            *Rgb::from_slice(&[181, 230, 29]),
-            *Rgb::from_slice(&[153, 217, 234]),
-            *Rgb::from_slice(&[112, 146, 190]),
-            *Rgb::from_slice(&[200, 191, 231]),
-        ];
-        let enigo = enigo::Enigo::new();
-        let state = device_query::DeviceState::new();
-        wait_for_keyup(Keycode::LControl, &state);
-        let (mut left, mut top) = enigo.mouse_location();
-        wait_for_keyup(Keycode::LControl, &state);
-        let (mut right, mut bottom) = enigo.mouse_location();
-        wait_for_keyup(Keycode::LControl, &state);
-        let (black_x, black_y) = enigo.mouse_location();
-        if right < left {
-            (right, left) = (left, right);
-        }
-        if bottom < top {
-            (top, bottom) = (bottom, top);
+    /// `allowed_colors` limits the image to those colors before it is painted, empty allows any
+    pub fn new(
+        img: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        options: PaintOptions,
+        calibration: Calibration,
+        app: AppProfile,
+        allowed_colors: Vec<Rgb<u8>>,
+    ) -> Self {
+        let input = backend::input(options.input);
+        let screen = backend::capture(options.capture);
+        Self::with_backends(
+            img,
+            options,
+            calibration,
+            app,
+            allowed_colors,
+            input,
+            screen,
+        )
+    }
+
+    fn with_backends(
+        img: ImageBuffer<Rgba<u8>, Vec<u8>>,
+        mut options: PaintOptions,
+        calibration: Calibration,
+        app: AppProfile,
+        allowed_colors: Vec<Rgb<u8>>,
+        input: Box<dyn Input>,
+        screen: Box<dyn Capture>,
+    ) -> Self {
+        let colors = app.presets.clone();
+        // Only the colors are planned exactly, the screen is still compared with verify_tolerance
+        if options.exact {
+            options.tolerance = 0.;
         }
+        if !app.has_color_dialog() {
+            options.color_limit = 0;
+        }
+        let Calibration {
+            left,
+            top,
+            right,
+            bottom,
+            black_x,
+            black_y,
+        } = calibration;
         let painting_width = right - left;
         let painting_height = bottom - top;
-        let horizontal_dots = (painting_width as f32 / DOT_WIDTH_FLOAT).ceil() as i32;
-        let vertical_dots = (painting_height as f32 / DOT_WIDTH_FLOAT).ceil() as i32;
-        let img = resize(
-            &img,
-            horizontal_dots as u32,
-            vertical_dots as u32,
-            image::imageops::FilterType::Gaussian,
-        );
-        Self {
-            enigo,
-            img: img.into(),
+        let horizontal_dots = (painting_width as f32 / options.dot_width as f32).ceil() as i32;
+        let vertical_dots = (painting_height as f32 / options.dot_width as f32).ceil() as i32;
+        let mut artist = Self {
+            input,
+            screen,
+            img: DynamicImage::new_rgba8(0, 0),
             left,
             top,
             black_x,
             black_y,
             width: horizontal_dots,
             height: vertical_dots,
-            colors,
             canvas_selected: false,
-            tolerance,
-            color_limit,
-        }
+            options,
+            paint_count: 0,
+            painted: None,
+            planned: None,
+            custom_slots: vec![],
+            background: None,
+            palette: None,
+            current_color: colors[0],
+            colors,
+            timing: options
+                .adaptive_timing
+                .then(|| TimingController::new(options.timing)),
+            stroke_count: 0,
+            app,
+            allowed_colors,
+        };
+        artist.new_image(img);
+        artist
     }
 
     fn new_image(&mut self, img: ImageBuffer<Rgba<u8>, Vec<u8>>) {
-        let img = resize(
-            &img,
+        self.img = self.prepare_image(&img).into();
+    }
+
+    /// Resizes the image to one pixel per dot and replaces its colors with the closest allowed ones
+    fn prepare_image(
+        &self,
+        img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut img = resize(
+            img,
             self.width as u32,
             self.height as u32,
-            image::imageops::FilterType::Gaussian,
+            self.options.resize_filter(),
         );
-        self.img = img.into();
+        if !self.allowed_colors.is_empty() {
+            for pixel in img.pixels_mut().filter(|pixel| pixel.0[3] != 0) {
+                let color = blend_with_matte(*pixel, self.options.matte);
+                let closest = self
+                    .allowed_colors
+                    .iter()
+                    .min_by(|a, b| {
+                        color_difference(color, **a).total_cmp(&color_difference(color, **b))
+                    })
+                    .unwrap();
+                *pixel = closest.to_rgba();
+            }
+        }
+        img
     }
 
-    fn paint_preprocess(&mut self) -> (Vec<PaintInstruction>, Vec<Rgb<u8>>, Rgb<u8>) {
+    fn paint_preprocess(
+        &mut self,
+        canvas: Option<&ImageBuffer<Rgb<u8>, Vec<u8>>>,
+    ) -> (Vec<PaintInstruction>, Vec<Rgb<u8>>, Option<Rgb<u8>>) {
         let mut instructions = vec![];
         let mut init_colors = vec![];
-        let mut total_colors: HashMap<Rgb<u8>, i32> = HashMap::new();
-        for (_, _, color) in self.img.pixels() {
-            let blended = blend_with_white(color);
-            let mut best_match = None;
-            let mut best_match_value = f32::INFINITY;
-            for (color, _) in total_colors.iter() {
-                let diff = color_difference(blended, *color);
-                if diff < best_match_value {
-                    best_match = Some(color.clone());
-                    best_match_value = diff;
-                }
-            }
-            if best_match_value > self.tolerance || best_match == None {
-                total_colors.insert(blended, 1);
-            } else {
-                *total_colors.get_mut(&best_match.unwrap()).unwrap() += 1;
-            }
-        }
-        let background = *total_colors
-            .iter()
-            .max_by_key(|(_, used)| **used)
-            .unwrap()
-            .0;
-        let mut draw_batches: [Vec<(i32, i32)>; 20] = Default::default();
+        let background = self.pick_background(canvas);
+        let mut draw_batches: Vec<Vec<(i32, i32)>> = vec![vec![]; self.colors.len()];
         let mut custom_draw_batches: Vec<(Rgb<u8>, Vec<(i32, i32)>)> = vec![];
         let mut pixels: Vec<(u32, u32, Rgb<u8>)> = self
             .img
             .pixels()
-            .map(|(x, y, color)| (x, y, blend_with_white(color)))
             .filter(|(_, _, color)| {
-                let diff = color_difference(*color, background);
-                diff > self.tolerance
+                !matches!(self.options.background, Background::None) || color.0[3] != 0
+            })
+            .map(|(x, y, color)| (x, y, blend_with_matte(color, self.options.matte)))
+            .filter(|(_, _, color)| match background {
+                Some(background) => color_difference(*color, background) > self.options.tolerance,
+                None => true,
+            })
+            .filter(|(x, y, color)| match canvas {
+                Some(canvas) => {
+                    color_difference(*color, *canvas.get_pixel(*x, *y)) > self.options.tolerance
+                }
+                None => true,
             })
             .collect();
-        if self.color_limit == 0 {
+        if self.options.exact {
+            self.check_exact(pixels.iter().map(|(_, _, color)| *color), background);
+        }
+        if self.options.color_limit == 0 {
             for (x, y, color) in pixels.iter().rev() {
                 let mut best_match = 0;
                 let mut best_match_value = f32::INFINITY;
@@ -210,29 +493,34 @@ impl Artist {
                         best_match_value = diff;
                     }
                 }
-                if best_match_value <= self.tolerance {
+                if best_match_value <= self.options.tolerance {
                     pixels.remove(i);
                     draw_batches[best_match].push((*x as i32, *y as i32));
                 }
             }
-            while pixels.len() != 0 && custom_draw_batches.len() < self.color_limit {
-                let mut total_colors: HashMap<Rgb<u8>, i32> = HashMap::new();
-                for (_, _, color) in &pixels {
-                    let mut best_match = None;
-                    let mut best_match_value = f32::INFINITY;
-                    for (color_2, _) in total_colors.iter() {
-                        let diff = color_difference(*color, *color_2);
-                        if diff < best_match_value {
-                            best_match = Some(color_2.clone());
-                            best_match_value = diff;
-                        }
-                    }
-                    if best_match_value > self.tolerance || best_match == None {
-                        total_colors.insert(*color, 1);
-                    } else {
-                        *total_colors.get_mut(&best_match.unwrap()).unwrap() += 1;
+            let mut seeds = match &self.palette {
+                Some(palette) => palette.custom.clone(),
+                None => self.custom_slots.clone(),
+            };
+            if let (Some(_), Some(background)) = (canvas, self.background) {
+                seeds.push(background);
+            }
+            for seed in seeds {
+                custom_draw_batches.push((seed, vec![]));
+                let index = custom_draw_batches.len() - 1;
+                for (i, (x, y, color)) in pixels.clone().iter().enumerate().rev() {
+                    let diff = color_difference(*color, seed);
+                    if diff <= self.options.tolerance {
+                        pixels.remove(i);
+                        custom_draw_batches[index].1.push((*x as i32, *y as i32));
                     }
                 }
+            }
+            while self.palette.is_none()
+                && !pixels.is_empty()
+                && custom_draw_batches.len() < self.options.color_limit
+            {
+                let total_colors = self.cluster_colors(pixels.iter().map(|(_, _, color)| *color));
                 let most_common = total_colors
                     .iter()
                     .max_by_key(|(_, used)| **used)
@@ -242,7 +530,7 @@ impl Artist {
                 let index = custom_draw_batches.len() - 1;
                 for (i, (x, y, color)) in pixels.clone().iter().enumerate().rev() {
                     let diff = color_difference(*color, *most_common);
-                    if diff <= self.tolerance {
+                    if diff <= self.options.tolerance {
                         pixels.remove(i);
                         custom_draw_batches[index].1.push((*x as i32, *y as i32));
                     }
@@ -262,18 +550,20 @@ impl Artist {
                     for (i, (color_2, _)) in custom_draw_batches.iter().enumerate() {
                         let diff = color_difference(color, *color_2);
                         if diff < best_match_value {
-                            best_match = i + 20;
+                            best_match = i + self.colors.len();
                             best_match_value = diff;
                         }
                     }
-                    let diff = color_difference(color, background);
-                    if diff < best_match_value {
-                        continue;
+                    if let Some(background) = background {
+                        let diff = color_difference(color, background);
+                        if diff < best_match_value {
+                            continue;
+                        }
                     }
-                    if best_match < 20 {
+                    if best_match < self.colors.len() {
                         draw_batches[best_match].push((x as i32, y as i32));
                     } else {
-                        custom_draw_batches[best_match - 20]
+                        custom_draw_batches[best_match - self.colors.len()]
                             .1
                             .push((x as i32, y as i32));
                     }
@@ -289,10 +579,27 @@ impl Artist {
                 final_instructions.append(&mut lines);
             }
         }
+        if let (true, Some(palette)) = (self.custom_slots.is_empty(), &self.palette) {
+            init_colors = palette
+                .custom
+                .iter()
+                .take(self.app.custom_slots)
+                .copied()
+                .collect();
+        }
         for (color, batch) in &mut custom_draw_batches {
             if batch.len() != 0 {
-                if init_colors.len() != 10 {
-                    let color_index = 20 + init_colors.len() as i32;
+                let slot = self
+                    .custom_slots
+                    .iter()
+                    .chain(init_colors.iter())
+                    .position(|slot| slot == color);
+                if let Some(slot) = slot {
+                    let color_index = (self.colors.len() + slot) as i32;
+                    final_instructions.push(PaintInstruction::Color(color_index));
+                } else if self.custom_slots.is_empty() && init_colors.len() < self.app.custom_slots
+                {
+                    let color_index = (self.colors.len() + init_colors.len()) as i32;
                     final_instructions.push(PaintInstruction::Color(color_index));
                     init_colors.push(*color);
                 } else {
@@ -306,6 +613,159 @@ impl Artist {
         (final_instructions, init_colors, background)
     }
 
+    fn pick_background(&self, canvas: Option<&ImageBuffer<Rgb<u8>, Vec<u8>>>) -> Option<Rgb<u8>> {
+        match (canvas, &self.palette, self.options.background) {
+            (Some(_), _, _) | (None, _, Background::None) => None,
+            (None, Some(palette), _) => palette.background,
+            (None, None, Background::Auto) => Some(self.usable_color(self.detect_background())),
+            (None, None, Background::Color(color)) => Some(self.usable_color(color)),
+        }
+    }
+
+    /// Fails unless the colors that aren't presets fit in the custom colors, the background only
+    /// needs one if the app can't set it with the color dialog
+    fn check_exact(&self, colors: impl Iterator<Item = Rgb<u8>>, background: Option<Rgb<u8>>) {
+        let mut custom: HashSet<Rgb<u8>> = colors.collect();
+        if let Some(background) = background.filter(|_| !self.app.has_color_dialog()) {
+            custom.insert(background);
+        }
+        custom.retain(|color| !self.colors.contains(color));
+        if custom.len() > self.app.custom_slots {
+            panic!(
+                "The image has {} colors besides the {} presets but only {} custom colors fit, use an image with fewer colors or leave out --exact",
+                custom.len(),
+                self.colors.len(),
+                self.app.custom_slots
+            );
+        }
+    }
+
+    /// The color itself, or the closest preset if the app has no color dialog to set it
+    fn usable_color(&self, color: Rgb<u8>) -> Rgb<u8> {
+        if self.app.has_color_dialog() {
+            return color;
+        }
+        self.colors[self.closest_preset(color)]
+    }
+
+    fn closest_preset(&self, color: Rgb<u8>) -> usize {
+        self.colors
+            .iter()
+            .map(|preset| color_difference(color, *preset))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+            .0
+    }
+
+    /// Traces the edges of the image into strokes in the sketch color
+    fn sketch_instructions(&self, sketch: Sketch) -> Vec<PaintInstruction> {
+        let mut instructions = vec![
+            PaintInstruction::SelectBrush,
+            PaintInstruction::SetMaxSize,
+            self.color_instruction(sketch.color),
+        ];
+        for stroke in sketch::trace_edges(&self.source_dots(), sketch.threshold) {
+            let points = stroke
+                .iter()
+                .map(|(x, y)| (x * self.options.dot_width, y * self.options.dot_width))
+                .collect();
+            instructions.push(PaintInstruction::Stroke(points));
+        }
+        instructions
+    }
+
+    fn detect_background(&self) -> Rgb<u8> {
+        let colors = self
+            .img
+            .pixels()
+            .map(|(_, _, color)| blend_with_matte(color, self.options.matte));
+        *self
+            .cluster_colors(colors)
+            .iter()
+            .max_by_key(|(_, used)| **used)
+            .unwrap()
+            .0
+    }
+
+    fn cluster_colors(&self, colors: impl Iterator<Item = Rgb<u8>>) -> HashMap<Rgb<u8>, i32> {
+        let mut total_colors: HashMap<Rgb<u8>, i32> = HashMap::new();
+        for color in colors {
+            let mut best_match = None;
+            let mut best_match_value = f32::INFINITY;
+            for (color_2, _) in total_colors.iter() {
+                let diff = color_difference(color, *color_2);
+                if diff < best_match_value {
+                    best_match = Some(*color_2);
+                    best_match_value = diff;
+                }
+            }
+            match best_match {
+                Some(best_match) if best_match_value <= self.options.tolerance => {
+                    *total_colors.get_mut(&best_match).unwrap() += 1;
+                }
+                _ => {
+                    total_colors.insert(color, 1);
+                }
+            }
+        }
+        total_colors
+    }
+
+    fn plan_palette<'a>(
+        &mut self,
+        images: impl Iterator<Item = &'a ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    ) {
+        let mut colors = vec![];
+        for img in images {
+            let img = self.prepare_image(img);
+            colors.extend(
+                img.pixels()
+                    .filter(|color| {
+                        !matches!(self.options.background, Background::None) || color.0[3] != 0
+                    })
+                    .map(|color| blend_with_matte(*color, self.options.matte)),
+            );
+        }
+        let background = match self.options.background {
+            Background::Auto => Some(
+                *self
+                    .cluster_colors(colors.iter().copied())
+                    .iter()
+                    .max_by_key(|(_, used)| **used)
+                    .unwrap()
+                    .0,
+            ),
+            Background::Color(color) => Some(color),
+            Background::None => None,
+        }
+        .map(|background| self.usable_color(background));
+        // Every frame paints with the planned colors, so all of them together have to fit
+        if self.options.exact {
+            self.check_exact(colors.iter().copied(), background);
+        }
+        colors.retain(|color| {
+            background.is_none_or(|background| {
+                color_difference(*color, background) > self.options.tolerance
+            }) && self
+                .colors
+                .iter()
+                .all(|preset| color_difference(*color, *preset) > self.options.tolerance)
+        });
+        let mut custom = vec![];
+        while !colors.is_empty() && custom.len() < self.options.color_limit {
+            let most_common = *self
+                .cluster_colors(colors.iter().copied())
+                .iter()
+                .max_by_key(|(_, used)| **used)
+                .unwrap()
+                .0;
+            colors.retain(|color| color_difference(*color, most_common) > self.options.tolerance);
+            custom.push(most_common);
+        }
+        self.palette = Some(Palette { background, custom });
+    }
+
     fn lines_from_points(&self, points: &mut Vec<(i32, i32)>) -> Vec<PaintInstruction> {
         let mut instructions = vec![];
         while points.len() != 0 {
@@ -390,29 +850,35 @@ impl Artist {
         &mut self,
         instructions: Vec<PaintInstruction>,
         init_colors: Vec<Rgb<u8>>,
-        background: Rgb<u8>,
+        background: Option<Rgb<u8>>,
     ) {
-        self.click(self.left, self.top);
-        shortcut(&[Key::Control, Key::A], &mut self.enigo);
-        self.enigo.key_click(Key::Delete);
-        self.select_square();
-        self.set_max_brush_size();
-        self.select_color_precise(background, false);
-        self.select_color_precise(background, true);
-        self.draw_square(0, 0, self.width - 1, self.height - 1);
+        if let Some(background) = background {
+            self.run(|app| &app.clear_canvas, background);
+            self.run(|app| &app.select_rectangle, background);
+            self.set_max_brush_size();
+            self.select_color_precise(background, false);
+            self.select_color_precise(background, true);
+            self.draw_square(0, 0, self.width - 1, self.height - 1);
+        }
         if init_colors.len() != 0 {
             for color in &init_colors {
-                self.create_color(*color);
+                self.create_color(*color, false);
             }
-            for i in 0..10 - init_colors.len() {
-                self.create_color(self.colors[i]);
+            for i in 0..self.app.custom_slots - init_colors.len() {
+                self.create_color(self.colors[i % self.colors.len()], false);
             }
+            self.custom_slots = init_colors;
         }
+        self.execute(instructions);
+    }
+
+    fn execute(&mut self, instructions: Vec<PaintInstruction>) {
         for instruction in instructions {
             match instruction {
                 PaintInstruction::Line(start_x, start_y, end_x, end_y) => {
                     self.draw_line(start_x, start_y, end_x, end_y)
                 }
+                PaintInstruction::Stroke(points) => self.draw_stroke(&points),
                 PaintInstruction::Color(index) => self.select_color(index),
                 PaintInstruction::ColorPrecise(color) => self.select_color_precise(color, false),
                 PaintInstruction::SelectBrush => self.select_brush(),
@@ -422,141 +888,955 @@ impl Artist {
     }
 
     pub fn paint(&mut self) {
-        let (instructions, init_colors, background) = self.paint_preprocess();
-        self.paint_from_preprocess(instructions, init_colors, background);
-    }
-
-    pub fn screenshot(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        sleep(LONG_SLEEP_TIME);
-        let monitor = Monitor::from_point(self.left, self.top).unwrap();
-        let img = monitor.capture_image().unwrap();
-        crop_imm(
-            &img,
-            self.left as u32,
-            self.top as u32,
-            (self.width * DOT_WIDTH - 1) as u32,
-            (self.height * DOT_WIDTH - 2) as u32,
+        if self.options.budget.is_set() {
+            self.search_settings(&[]);
+        }
+        let canvas = if self.options.overlay {
+            let screenshot = self.screenshot();
+            Some(self.dot_colors(&screenshot))
+        } else {
+            self.painted.take()
+        };
+        let (mut instructions, init_colors, background) = match self.options.sketch {
+            // Only the lines are painted so every frame starts from a cleared canvas
+            Some(sketch) if !sketch.fill => (
+                vec![],
+                vec![],
+                self.pick_background(canvas.as_ref().filter(|_| self.options.overlay)),
+            ),
+            _ => self.paint_preprocess(canvas.as_ref()),
+        };
+        if let Some(sketch) = self.options.sketch {
+            instructions.append(&mut self.sketch_instructions(sketch));
+        }
+        let planned = self.render_plan(&instructions, &init_colors, background);
+        // Dots the plan leaves alone keep whatever the canvas shows, which is only known without
+        // a screenshot when the background covers all of it
+        let base = match canvas {
+            Some(canvas) => canvas,
+            None if background.is_none() => {
+                let screenshot = self.screenshot();
+                self.dot_colors(&screenshot)
+            }
+            None => self.source_dots(),
+        };
+        let painted = self.planned_dots(&planned, &base);
+        self.paint_from_preprocess(instructions, init_colors.clone(), background);
+        if background.is_some() {
+            self.background = background;
+        }
+        if self.options.verify {
+            self.verify(&planned);
+        }
+        self.paint_count += 1;
+        if self.options.report {
+            self.report(&painted);
+        }
+        self.painted = Some(painted);
+        self.planned = Some(planned);
+    }
+
+    /// Picks the tolerance and color limit for the budget, over all `frames` of an animation or
+    /// the image alone if there are none
+    fn search_settings(&mut self, frames: &[&ImageBuffer<Rgba<u8>, Vec<u8>>]) {
+        let budget = self.options.budget;
+        let tolerance = if budget.has_cost_limit() {
+            self.search(
+                MIN_SEARCHED_TOLERANCE,
+                MAX_SEARCHED_TOLERANCE,
+                |artist, tolerance| {
+                    artist.options.tolerance = tolerance;
+                    artist.estimate_plan(frames).fits(&budget)
+                },
+            )
+        } else {
+            self.search(
+                MAX_SEARCHED_TOLERANCE,
+                MIN_SEARCHED_TOLERANCE,
+                |artist, tolerance| {
+                    artist.options.tolerance = tolerance;
+                    artist.estimate_plan(frames).meets(&budget)
+                },
+            )
+        };
+        self.options.tolerance = tolerance;
+        if budget.search_limit && self.app.has_color_dialog() {
+            let limit = if budget.has_cost_limit() {
+                self.search(MAX_SEARCHED_LIMIT as f32, 0., |artist, limit| {
+                    artist.options.color_limit = limit.round() as usize;
+                    artist.estimate_plan(frames).fits(&budget)
+                })
+            } else {
+                self.search(0., MAX_SEARCHED_LIMIT as f32, |artist, limit| {
+                    artist.options.color_limit = limit.round() as usize;
+                    artist.estimate_plan(frames).meets(&budget)
+                })
+            };
+            self.options.color_limit = limit.round() as usize;
+        }
+        let estimate = self.estimate_plan(frames);
+        if !estimate.fits(&budget) || !estimate.meets(&budget) {
+            println!("Could not meet the requested budget, using the closest settings");
+        }
+        println!(
+            "Using tolerance {:.2} and limit {} ({} strokes, about {}s, PSNR {:.2} dB)",
+            self.options.tolerance,
+            self.options.color_limit,
+            estimate.strokes,
+            estimate.duration.as_secs(),
+            estimate.quality
+        );
+        self.options.budget = Budget::default();
+    }
+
+    /// Binary search for the value closest to `goal` that still passes `accept`, assuming that
+    /// every value between a passing value and `safe` passes too
+    fn search(&mut self, goal: f32, safe: f32, accept: impl Fn(&mut Self, f32) -> bool) -> f32 {
+        if accept(self, goal) {
+            return goal;
+        }
+        let (mut passing, mut failing) = (safe, goal);
+        for _ in 0..SEARCH_STEPS {
+            let middle = (passing + failing) / 2.;
+            if accept(self, middle) {
+                passing = middle;
+            } else {
+                failing = middle;
+            }
+        }
+        passing
+    }
+
+    /// How many inputs the steps of an action send and how long they wait
+    fn action_cost(&self, steps: &[Step]) -> (u32, Duration) {
+        let mut inputs = 0;
+        let mut sleeps = Duration::ZERO;
+        for step in steps {
+            match step {
+                Step::Keys(presses) => {
+                    for (keys, times) in presses {
+                        inputs += (2 * keys.len() * times) as u32;
+                    }
+                }
+                Step::Type(text) => {
+                    inputs += 2 * app::fill_color(text, Rgb([0, 0, 0])).chars().count() as u32
+                }
+                Step::Click(..) => {
+                    inputs += 3;
+                    sleeps += self.options.timing.small_sleep;
+                }
+                Step::Wait(wait) => sleeps += self.options.timing.get(*wait),
+            }
+        }
+        (inputs, sleeps)
+    }
+
+    /// What setting a color through the color dialog costs, or clicking a preset without one
+    fn set_color_cost(&self) -> (u32, Duration) {
+        if !self.app.has_color_dialog() {
+            return (3, self.options.timing.small_sleep);
+        }
+        let (open_inputs, open_sleeps) = self.action_cost(&self.app.open_color_dialog);
+        let (enter_inputs, enter_sleeps) = self.action_cost(&self.app.enter_color);
+        (
+            open_inputs + enter_inputs,
+            open_sleeps + enter_sleeps + self.options.timing.medium_sleep,
         )
-        .to_image()
+    }
+
+    /// Estimates painting the image, or every one of `frames` with the palette planned for all of
+    /// them like an animation is painted. The quality is that of the worst frame
+    fn estimate_plan(&mut self, frames: &[&ImageBuffer<Rgba<u8>, Vec<u8>>]) -> PlanEstimate {
+        // Estimating sets up the custom colors and background like painting does, which is undone
+        // after
+        let img = self.img.clone();
+        let custom_slots = self.custom_slots.clone();
+        let background = self.background;
+        let palette = self.palette.take();
+        let mut total = PlanEstimate {
+            strokes: 0,
+            duration: Duration::ZERO,
+            quality: f32::INFINITY,
+        };
+        if frames.is_empty() {
+            total = self.estimate_frame(None).0;
+        } else {
+            self.plan_palette(frames.iter().copied());
+            let mut canvas = None;
+            for frame in frames {
+                self.new_image((*frame).clone());
+                let (estimate, painted) = self.estimate_frame(canvas.as_ref());
+                total.strokes += estimate.strokes;
+                total.duration += estimate.duration;
+                total.quality = total.quality.min(estimate.quality);
+                canvas = Some(painted);
+            }
+        }
+        self.img = img;
+        self.custom_slots = custom_slots;
+        self.background = background;
+        self.palette = palette;
+        total
+    }
+
+    /// Estimates painting the current image over `canvas` and returns the dots it leaves, keeping
+    /// the custom colors and background it sets like painting would
+    fn estimate_frame(
+        &mut self,
+        canvas: Option<&ImageBuffer<Rgb<u8>, Vec<u8>>>,
+    ) -> (PlanEstimate, ImageBuffer<Rgb<u8>, Vec<u8>>) {
+        let (instructions, init_colors, background) = self.paint_preprocess(canvas);
+        let planned = self.render_plan(&instructions, &init_colors, background);
+        let source = self.source_dots();
+        let painted = self.planned_dots(&planned, canvas.unwrap_or(&source));
+        let quality = report::psnr(&source, &painted);
+        if background.is_some() {
+            self.background = background;
+        }
+        let mut inputs = 0;
+        let mut sleeps = Duration::ZERO;
+        if background.is_some() {
+            for action in [
+                &self.app.clear_canvas,
+                &self.app.select_rectangle,
+                &self.app.max_brush_size,
+                &self.app.select_color_2,
+                &self.app.select_color_1,
+                &self.app.finish_rectangle,
+            ] {
+                let (step_inputs, step_sleeps) = self.action_cost(action);
+                inputs += step_inputs;
+                sleeps += step_sleeps;
+            }
+            // Color 1 and color 2 are both set to the background
+            let (color_inputs, color_sleeps) = self.set_color_cost();
+            inputs += 2 * color_inputs;
+            sleeps += 2 * color_sleeps;
+            let (width, height) = (
+                (self.width - 1) * self.options.dot_width,
+                (self.height - 1) * self.options.dot_width,
+            );
+            inputs += 3;
+            sleeps += self.options.timing.small_sleep + self.drag_time((0, 0), (width, height));
+        }
+        if !init_colors.is_empty() {
+            let (slot_inputs, slot_sleeps) = self.set_color_cost();
+            inputs += self.app.custom_slots as u32 * slot_inputs;
+            sleeps += self.app.custom_slots as u32 * slot_sleeps;
+            self.custom_slots = init_colors;
+        }
+        let mut strokes = 0;
+        for instruction in &instructions {
+            match instruction {
+                PaintInstruction::Line(start_x, start_y, end_x, end_y) => {
+                    strokes += 1;
+                    inputs += 4;
+                    sleeps += self.options.timing.small_sleep
+                        + self.drag_time(
+                            (
+                                start_x * self.options.dot_width,
+                                start_y * self.options.dot_width,
+                            ),
+                            (
+                                end_x * self.options.dot_width,
+                                end_y * self.options.dot_width,
+                            ),
+                        );
+                }
+                PaintInstruction::Stroke(points) => {
+                    strokes += 1;
+                    inputs += 2 + points.len() as u32;
+                    sleeps += self.options.timing.small_sleep;
+                    for segment in points.windows(2) {
+                        sleeps += self.drag_time(segment[0], segment[1]);
+                    }
+                }
+                PaintInstruction::Color(_) => {
+                    inputs += 6;
+                    sleeps += 2 * self.options.timing.small_sleep;
+                }
+                PaintInstruction::ColorPrecise(_) => {
+                    let (step_inputs, step_sleeps) = self.set_color_cost();
+                    inputs += step_inputs;
+                    sleeps += step_sleeps;
+                }
+                PaintInstruction::SetMaxSize => {
+                    let (step_inputs, step_sleeps) = self.action_cost(&self.app.max_brush_size);
+                    inputs += step_inputs;
+                    sleeps += step_sleeps;
+                }
+                PaintInstruction::SelectBrush => {
+                    let (step_inputs, step_sleeps) = self.action_cost(&self.app.select_brush);
+                    inputs += step_inputs;
+                    sleeps += step_sleeps;
+                }
+            }
+        }
+        let estimate = PlanEstimate {
+            strokes,
+            duration: sleeps + INPUT_TIME * inputs,
+            quality,
+        };
+        (estimate, painted)
+    }
+
+    fn source_dots(&self) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            blend_with_matte(self.img.get_pixel(x, y), self.options.matte)
+        })
+    }
+
+    fn planned_dots(
+        &self,
+        planned: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+        base: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    ) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let color = planned.get_pixel(x, y);
+            if color.0[3] == 0 {
+                *base.get_pixel(x, y)
+            } else {
+                color.to_rgb()
+            }
+        })
+    }
+
+    fn report(&mut self, planned: &ImageBuffer<Rgb<u8>, Vec<u8>>) {
+        let source = self.source_dots();
+        let screenshot = self.screenshot();
+        let painted = self.dot_colors(&screenshot);
+        println!("Planned: {}", Metrics::compare(&source, planned));
+        println!("Painted: {}", Metrics::compare(&source, &painted));
+        let difference = report::difference_image(&source, &painted);
+        let path = if self.paint_count == 1 {
+            "report.png".to_string()
+        } else {
+            format!("report_{}.png", self.paint_count)
+        };
+        report::side_by_side(
+            &[&source, planned, &painted, &difference],
+            self.options.dot_width as u32,
+        )
+        .save(path)
+        .unwrap();
+    }
+
+    fn render_plan(
+        &self,
+        instructions: &[PaintInstruction],
+        init_colors: &[Rgb<u8>],
+        background: Option<Rgb<u8>>,
+    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut planned = ImageBuffer::from_pixel(
+            self.width as u32,
+            self.height as u32,
+            match background {
+                Some(background) => background.to_rgba(),
+                None => Rgba([0, 0, 0, 0]),
+            },
+        );
+        let mut color = self.colors[0];
+        for instruction in instructions {
+            match instruction {
+                PaintInstruction::Line(start_x, start_y, end_x, end_y) => {
+                    for x in *start_x.min(end_x)..=*start_x.max(end_x) {
+                        for y in *start_y.min(end_y)..=*start_y.max(end_y) {
+                            planned.put_pixel(x as u32, y as u32, color.to_rgba());
+                        }
+                    }
+                }
+                PaintInstruction::Color(index) if (*index as usize) < self.colors.len() => {
+                    color = self.colors[*index as usize]
+                }
+                PaintInstruction::Color(index) => {
+                    color = *self
+                        .custom_slots
+                        .iter()
+                        .chain(init_colors)
+                        .nth(*index as usize - self.colors.len())
+                        .unwrap()
+                }
+                PaintInstruction::ColorPrecise(precise) => color = *precise,
+                PaintInstruction::Stroke(points) => {
+                    // The last point is paired with itself so single point strokes are drawn too
+                    let ends = points.iter().skip(1).chain(points.last());
+                    for (&(start_x, start_y), &(end_x, end_y)) in points.iter().zip(ends) {
+                        let steps = (end_x - start_x).abs().max((end_y - start_y).abs()).max(1);
+                        for step in 0..=steps {
+                            let x = (start_x + (end_x - start_x) * step / steps)
+                                / self.options.dot_width;
+                            let y = (start_y + (end_y - start_y) * step / steps)
+                                / self.options.dot_width;
+                            if x >= 0 && y >= 0 && x < self.width && y < self.height {
+                                planned.put_pixel(x as u32, y as u32, color.to_rgba());
+                            }
+                        }
+                    }
+                }
+                PaintInstruction::SelectBrush | PaintInstruction::SetMaxSize => {}
+            }
+        }
+        planned
+    }
+
+    /// Compares the painting with the plan and repaints the wrong dots, checking again after every
+    /// pass so the last report shows what is left on the screen
+    fn verify(&mut self, planned: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        for pass in 0..=self.options.max_passes {
+            let screenshot = self.screenshot();
+            let painted = self.dot_colors(&screenshot);
+            let mut totals: HashMap<Rgb<u8>, usize> = HashMap::new();
+            let mut mismatches: HashMap<Rgb<u8>, Vec<(i32, i32)>> = HashMap::new();
+            for (x, y, color) in planned.enumerate_pixels() {
+                if color.0[3] == 0 {
+                    continue;
+                }
+                let color = color.to_rgb();
+                *totals.entry(color).or_default() += 1;
+                if color_difference(color, *painted.get_pixel(x, y)) > self.options.verify_tolerance
+                {
+                    mismatches
+                        .entry(color)
+                        .or_default()
+                        .push((x as i32, y as i32));
+                }
+            }
+            let mismatch_count: usize = mismatches.values().map(|dots| dots.len()).sum();
+            match pass {
+                0 => println!("Verification: {mismatch_count} mismatched dots"),
+                _ => println!("After correction pass {pass}: {mismatch_count} mismatched dots"),
+            }
+            let mut report: Vec<_> = mismatches.iter().collect();
+            report.sort_by_key(|(_, dots)| Reverse(dots.len()));
+            for (color, dots) in report {
+                println!(
+                    "  #{:02X?}{:02X?}{:02X?}: {} of {} dots wrong",
+                    color.0[0],
+                    color.0[1],
+                    color.0[2],
+                    dots.len(),
+                    totals[color]
+                );
+            }
+            if mismatch_count <= self.options.verify_threshold || pass == self.options.max_passes {
+                return;
+            }
+            let mut instructions =
+                vec![PaintInstruction::SelectBrush, PaintInstruction::SetMaxSize];
+            for (color, mut dots) in mismatches {
+                instructions.push(self.color_instruction(color));
+                instructions.append(&mut self.lines_from_points(&mut dots));
+            }
+            self.execute(instructions);
+        }
+    }
+
+    /// Draws svg strokes as mouse drags, `width` and `height` are the size of the svg the points
+    /// are in
+    pub fn paint_strokes(&mut self, strokes: &[Stroke], width: f32, height: f32) {
+        let scale_x = (self.width * self.options.dot_width) as f32 / width;
+        let scale_y = (self.height * self.options.dot_width) as f32 / height;
+        let mut instructions = vec![PaintInstruction::SelectBrush, PaintInstruction::SetMaxSize];
+        let mut current_color = None;
+        for stroke in strokes {
+            let mut points: Vec<(i32, i32)> = stroke
+                .points
+                .iter()
+                .map(|(x, y)| ((x * scale_x).round() as i32, (y * scale_y).round() as i32))
+                .collect();
+            points.dedup();
+            if current_color != Some(stroke.color) {
+                instructions.push(self.color_instruction(stroke.color));
+                current_color = Some(stroke.color);
+            }
+            instructions.push(PaintInstruction::Stroke(points));
+        }
+        // The strokes end up in the saved dots and palette like the rest of the painting
+        let strokes = self.render_plan(&instructions, &[], None);
+        self.execute(instructions);
+        if let Some(planned) = &mut self.planned {
+            for (x, y, color) in strokes.enumerate_pixels() {
+                if color.0[3] != 0 {
+                    planned.put_pixel(x, y, *color);
+                }
+            }
+        }
+        if let Some(painted) = self.painted.take() {
+            self.painted = Some(self.planned_dots(&strokes, &painted));
+        }
+    }
+
+    /// Picks the cheapest way to select a color, a preset or custom color within the tolerance if
+    /// there is one and the color dialog otherwise
+    fn color_instruction(&self, color: Rgb<u8>) -> PaintInstruction {
+        let preset = self.closest_preset(color);
+        let preset_diff = color_difference(color, self.colors[preset]);
+        if preset_diff <= self.options.tolerance || self.options.color_limit == 0 {
+            return PaintInstruction::Color(preset as i32);
+        }
+        let slot = self
+            .custom_slots
+            .iter()
+            .position(|slot| color_difference(color, *slot) <= self.options.tolerance);
+        match slot {
+            Some(slot) => PaintInstruction::Color((self.colors.len() + slot) as i32),
+            None => PaintInstruction::ColorPrecise(color),
+        }
+    }
+
+    pub fn screenshot(&mut self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        sleep(self.options.timing.long_sleep);
+        let mut img = self.capture();
+        if self.timing.is_some() {
+            let settled = img == self.capture();
+            self.record_timing(Wait::Long, settled, "the canvas was still changing");
+            if !settled {
+                sleep(self.options.timing.long_sleep);
+                img = self.capture();
+            }
+        }
+        img
+    }
+
+    fn capture_monitor(&self) -> Screenshot {
+        self.screen.capture(self.left, self.top)
+    }
+
+    /// The painting as it is on the screen right now, in the logical pixels the mouse moves in
+    fn capture(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.capture_monitor().crop(
+            self.left,
+            self.top,
+            (self.width * self.options.dot_width - 1) as u32,
+            (self.height * self.options.dot_width - 2) as u32,
+        )
+    }
+
+    /// The planned dots, scaled up to the size of the painting
+    pub fn preview(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let planned = match &self.painted {
+            Some(painted) => painted.clone(),
+            None => self.source_dots(),
+        };
+        resize(
+            &DynamicImage::from(planned).to_rgba8(),
+            (self.width * self.options.dot_width) as u32,
+            (self.height * self.options.dot_width) as u32,
+            image::imageops::FilterType::Nearest,
+        )
+    }
+
+    /// The dots of the painting at the resolution it is painted in
+    pub fn dots(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.planned.clone().expect("Nothing has been painted")
+    }
+
+    /// Every color the painting uses, the background first, then the presets and then the custom
+    /// colors
+    pub fn used_colors(&self) -> Vec<Rgb<u8>> {
+        let dots = self.planned.as_ref().expect("Nothing has been painted");
+        let dot_colors = dots
+            .pixels()
+            .filter(|pixel| pixel.0[3] != 0)
+            .map(|pixel| pixel.to_rgb());
+        let used: HashSet<Rgb<u8>> = dot_colors.clone().collect();
+        let mut colors = vec![];
+        for color in self
+            .background
+            .iter()
+            .chain(&self.colors)
+            .copied()
+            .chain(dot_colors)
+        {
+            if used.contains(&color) && !colors.contains(&color) {
+                colors.push(color);
+            }
+        }
+        colors
+    }
+
+    /// The image at the resolution it is painted in
+    pub fn resized(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        self.img.to_rgba8()
+    }
+
+    fn dot_colors(&self, img: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let pixel = img.get_pixel(
+                (x * self.options.dot_width as u32).min(img.width() - 1),
+                (y * self.options.dot_width as u32).min(img.height() - 1),
+            );
+            pixel.to_rgb()
+        })
     }
 
     fn click(&mut self, x: i32, y: i32) {
-        self.enigo.mouse_move_to(x, y);
-        self.enigo.mouse_click(MouseButton::Left);
-        sleep(SMALL_SLEEP_TIME);
-        if self.enigo.mouse_location() != (x, y) {
+        self.input.move_to(x, y);
+        self.input.click();
+        sleep(self.options.timing.small_sleep);
+        if self.input.location() != (x, y) {
             panic!("Movement detected after clicking");
         }
     }
 
     fn drag(&mut self, start_x: i32, start_y: i32, end_x: i32, end_y: i32) {
-        self.enigo.mouse_move_to(start_x, start_y);
-        self.enigo.mouse_down(MouseButton::Left);
-        self.enigo.mouse_move_to(end_x, end_y);
-        self.enigo.mouse_up(MouseButton::Left);
-        sleep(SMALL_SLEEP_TIME);
-        if self.enigo.mouse_location() != (end_x, end_y) {
+        self.input.move_to(start_x, start_y);
+        self.input.button_down();
+        self.drag_to((start_x, start_y), (end_x, end_y));
+        self.input.button_up();
+        sleep(self.options.timing.small_sleep);
+        if self.input.location() != (end_x, end_y) {
             panic!("Movement detected after dragging");
         }
     }
 
+    /// Moves the mouse while the button is held, through evenly spaced points if drags are smoothed
+    fn drag_to(&mut self, from: (i32, i32), to: (i32, i32)) {
+        let Some(drag) = self.smoothed_drag() else {
+            self.input.move_to(to.0, to.1);
+            return;
+        };
+        let (dx, dy) = ((to.0 - from.0) as f32, (to.1 - from.1) as f32);
+        let moves = ((dx.hypot(dy) / drag.spacing).ceil() as u32).max(1);
+        let interval = self.drag_time(from, to) / moves;
+        for i in 1..=moves {
+            let progress = drag.easing.apply(i as f32 / moves as f32);
+            self.input.move_to(
+                from.0 + (dx * progress).round() as i32,
+                from.1 + (dy * progress).round() as i32,
+            );
+            sleep(interval);
+        }
+    }
+
+    fn drag_time(&self, from: (i32, i32), to: (i32, i32)) -> Duration {
+        match self.smoothed_drag() {
+            Some(drag) => {
+                let length = ((to.0 - from.0) as f32).hypot((to.1 - from.1) as f32);
+                Duration::from_secs_f32(length / drag.speed)
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    /// How drags are smoothed, a spacing of 0 or less moves straight to the end like no smoothing
+    fn smoothed_drag(&self) -> Option<Drag> {
+        self.options.drag.filter(|drag| drag.spacing > 0.)
+    }
+
+    fn draw_stroke(&mut self, points: &[(i32, i32)]) {
+        let points: Vec<(i32, i32)> = points
+            .iter()
+            .map(|(x, y)| (x + self.left, y + self.top))
+            .collect();
+        let (start_x, start_y) = points[0];
+        let (end_x, end_y) = points[points.len() - 1];
+        if !self.canvas_selected {
+            self.click(start_x, start_y);
+            self.canvas_selected = true;
+        }
+        self.input.move_to(start_x, start_y);
+        self.input.button_down();
+        for segment in points.windows(2) {
+            self.drag_to(segment[0], segment[1]);
+        }
+        self.input.button_up();
+        sleep(self.options.timing.small_sleep);
+        if self.input.location() != (end_x, end_y) {
+            panic!("Movement detected after drawing a stroke");
+        }
+        self.check_stroke(end_x, end_y);
+    }
+
     fn draw_line(&mut self, start_x: i32, start_y: i32, end_x: i32, end_y: i32) {
         let (start_x, start_y) = (
-            start_x * DOT_WIDTH + self.left,
-            start_y * DOT_WIDTH + self.top,
+            start_x * self.options.dot_width + self.left,
+            start_y * self.options.dot_width + self.top,
+        );
+        let (end_x, end_y) = (
+            end_x * self.options.dot_width + self.left,
+            end_y * self.options.dot_width + self.top,
         );
-        let (end_x, end_y) = (end_x * DOT_WIDTH + self.left, end_y * DOT_WIDTH + self.top);
         if !self.canvas_selected {
             self.click(start_x, start_y);
             self.canvas_selected = true;
         }
         self.drag(start_x, start_y, end_x, end_y);
+        self.check_stroke(end_x, end_y);
+    }
+
+    /// Checks every few strokes that the end of the stroke already has the selected color, `x` and
+    /// `y` are screen coordinates
+    fn check_stroke(&mut self, x: i32, y: i32) {
+        if self.timing.is_none() {
+            return;
+        }
+        self.stroke_count += 1;
+        if !self.stroke_count.is_multiple_of(STROKE_CHECK_INTERVAL) {
+            return;
+        }
+        let img = self.capture();
+        let pixel = img.get_pixel(
+            ((x - self.left).max(0) as u32).min(img.width() - 1),
+            ((y - self.top).max(0) as u32).min(img.height() - 1),
+        );
+        let painted =
+            color_difference(pixel.to_rgb(), self.current_color) <= self.options.verify_tolerance;
+        self.record_timing(Wait::Small, painted, "the last stroke wasn't painted yet");
+    }
+
+    fn record_timing(&mut self, wait: Wait, kept_up: bool, problem: &str) {
+        if let Some(controller) = &mut self.timing {
+            controller.record(&mut self.options.timing, wait, kept_up, problem);
+        }
+    }
+
+    /// Prints how adaptive timing picked the waits
+    pub fn report_timing(&self) {
+        if let Some(controller) = &self.timing {
+            controller.report(&self.options.timing);
+        }
     }
 
     fn draw_square(&mut self, start_x: i32, start_y: i32, end_x: i32, end_y: i32) {
         let (start_x, start_y) = (
-            start_x * DOT_WIDTH + self.left + 2,
-            start_y * DOT_WIDTH + self.top + 2,
+            start_x * self.options.dot_width + self.left + 2,
+            start_y * self.options.dot_width + self.top + 2,
+        );
+        let (end_x, end_y) = (
+            end_x * self.options.dot_width + self.left,
+            end_y * self.options.dot_width + self.top,
         );
-        let (end_x, end_y) = (end_x * DOT_WIDTH + self.left, end_y * DOT_WIDTH + self.top);
         self.drag(start_x, start_y, end_x, end_y);
-        self.click(start_x, start_y - 20);
+        self.run(|app| &app.finish_rectangle, self.current_color);
     }
 
     fn select_color(&mut self, color_index: i32) {
-        let column = color_index % 10;
-        let row = (color_index - column) / 10;
-        let x = column * COLOR_SPACING + self.black_x;
-        let y = row * COLOR_SPACING + self.black_y;
+        let (x, y) = self.preset_position(color_index);
         self.click(x, y);
         self.canvas_selected = false;
+        self.current_color = if (color_index as usize) < self.colors.len() {
+            self.colors[color_index as usize]
+        } else {
+            self.custom_slots
+                .get(color_index as usize - self.colors.len())
+                .copied()
+                .unwrap_or(self.current_color)
+        };
+        if self.options.confirm_colors && !self.color_1_matches(self.current_color) {
+            self.click(x, y);
+            if !self.color_1_matches(self.current_color) {
+                panic!("Color 1 didn't change after clicking color preset {color_index}");
+            }
+        }
+    }
+
+    /// Where the preset, or the custom color after the presets, is on the screen
+    fn preset_position(&self, color_index: i32) -> (i32, i32) {
+        let spacing = self.options.color_spacing;
+        let grid = |index: usize, columns: usize| {
+            (
+                (index % columns) as i32 * spacing,
+                (index / columns) as i32 * spacing,
+            )
+        };
+        let index = color_index as usize;
+        if let Some(slot) = index.checked_sub(self.colors.len()) {
+            let [origin_x, origin_y] = self.app.custom_origin(spacing);
+            let (x, y) = grid(slot, self.app.custom_columns());
+            return (self.black_x + origin_x + x, self.black_y + origin_y + y);
+        }
+        let (x, y) = match self.app.swatches.get(index) {
+            Some([x, y]) => (*x, *y),
+            None => grid(index, self.app.columns),
+        };
+        let (origin_x, origin_y) = self.options.palette_origin;
+        (self.black_x + origin_x + x, self.black_y + origin_y + y)
     }
 
     fn select_color_precise(&mut self, color: Rgb<u8>, secondary: bool) {
+        if !self.app.has_color_dialog() {
+            let preset = self.closest_preset(color);
+            if secondary {
+                self.run(|app| &app.select_color_2, color);
+                let (x, y) = self.preset_position(preset as i32);
+                self.click(x, y);
+                self.run(|app| &app.select_color_1, self.current_color);
+            } else {
+                self.select_color(preset as i32);
+            }
+            return;
+        }
         if secondary {
-            alt_sequence(&[Key::Num2], &mut self.enigo);
-            self.create_color(color);
-            alt_sequence(&[Key::Num1], &mut self.enigo);
+            self.run(|app| &app.select_color_2, color);
+            self.create_color(color, true);
+            self.run(|app| &app.select_color_1, color);
         } else {
-            self.create_color(color);
+            self.create_color(color, false);
         }
     }
 
-    fn create_color(&mut self, color: Rgb<u8>) {
-        alt_sequence(&[Key::E, Key::C], &mut self.enigo);
-        for _ in 0..4 {
-            self.enigo.key_click(Key::Tab);
+    /// Sets color 1, or color 2 if `secondary` is set, through the edit colors dialog
+    fn create_color(&mut self, color: Rgb<u8>, secondary: bool) {
+        self.canvas_selected = false;
+        if !secondary {
+            self.current_color = color;
         }
-        shortcut(&[Key::Control, Key::A], &mut self.enigo);
-        self.enigo.key_sequence(&format!(
-            "#{:02X?}{:02X?}{:02X?}",
-            color.0[0], color.0[1], color.0[2]
-        ));
-        for _ in 0..8 {
-            self.enigo.key_click(Key::Tab);
+        if !self.options.confirm_colors {
+            self.open_color_dialog();
+            self.enter_color(color);
+            sleep(self.options.timing.medium_sleep);
+            return;
         }
-        self.enigo.key_click(Key::Return);
-        self.canvas_selected = false;
-        sleep(MEDIUM_SLEEP_TIME);
+        for attempt in 1..=DIALOG_ATTEMPTS {
+            match self.try_create_color(color, secondary) {
+                Ok(()) => return,
+                Err(problem) => println!(
+                    "Setting the color to #{:02X?}{:02X?}{:02X?} failed, {problem} (attempt {attempt} of {DIALOG_ATTEMPTS})",
+                    color.0[0], color.0[1], color.0[2]
+                ),
+            }
+        }
+        panic!(
+            "Could not set the color to #{:02X?}{:02X?}{:02X?}",
+            color.0[0], color.0[1], color.0[2]
+        );
     }
 
-    fn set_max_brush_size(&mut self) {
-        alt_sequence(&[Key::S, Key::Z], &mut self.enigo);
-        sleep(MEDIUM_SLEEP_TIME);
-        self.enigo.key_click(Key::UpArrow);
-        self.enigo.key_click(Key::Return);
+    fn open_color_dialog(&mut self) {
+        let before = self.timing.is_some().then(|| self.capture_monitor());
+        self.run(|app| &app.open_color_dialog, self.current_color);
+        if let Some(before) = before {
+            sleep(self.options.timing.medium_sleep);
+            let opened = screen_changed(&before.image, &self.capture_monitor().image);
+            self.record_timing(Wait::Medium, opened, "the color dialog wasn't open yet");
+            if !opened {
+                sleep(self.options.timing.medium_sleep);
+            }
+        }
     }
 
-    fn select_brush(&mut self) {
-        alt_sequence(&[Key::B], &mut self.enigo);
+    /// Types the hex code into the open edit colors dialog and closes it
+    fn enter_color(&mut self, color: Rgb<u8>) {
+        self.run(|app| &app.enter_color, color);
     }
 
-    fn select_square(&mut self) {
-        for _ in 0..22 {
-            self.enigo.key_click(Key::Tab);
+    /// Goes through the edit colors dialog one step at a time, checking the screen after each step
+    fn try_create_color(&mut self, color: Rgb<u8>, secondary: bool) -> Result<(), &'static str> {
+        let before = self.capture_monitor();
+        self.run(|app| &app.open_color_dialog, color);
+        let opened = self.wait_for_screen(|screen| screen_changed(&before.image, screen));
+        if let Some(polls) = opened {
+            self.record_timing(Wait::Medium, polls == 1, "the color dialog wasn't open yet");
+        } else {
+            return Err("the dialog didn't open");
         }
-        for _ in 0..7 {
-            self.enigo.key_click(Key::LeftArrow);
+        self.enter_color(color);
+        if self
+            .wait_for_screen(|screen| !screen_changed(&before.image, screen))
+            .is_none()
+        {
+            self.input.key_click(Key::Escape);
+            sleep(self.options.timing.medium_sleep);
+            return Err("the dialog didn't close");
         }
-        for _ in 0..2 {
-            self.enigo.key_click(Key::UpArrow);
+        if !secondary && !self.color_1_matches(color) {
+            return Err("color 1 has a different color");
         }
-        for _ in 0..3 {
-            self.enigo.key_click(Key::RightArrow);
+        Ok(())
+    }
+
+    /// Polls the screen until `done` accepts it and returns how many screenshots that took
+    fn wait_for_screen(
+        &mut self,
+        done: impl Fn(&ImageBuffer<Rgba<u8>, Vec<u8>>) -> bool,
+    ) -> Option<usize> {
+        for poll in 1..=DIALOG_POLLS {
+            sleep(self.options.timing.medium_sleep);
+            if done(&self.capture_monitor().image) {
+                return Some(poll);
+            }
         }
-        self.enigo.key_click(Key::Return);
-        sleep(MEDIUM_SLEEP_TIME);
-        for _ in 0..2 {
-            self.enigo.key_click(Key::Tab);
+        None
+    }
+
+    /// Samples the color 1 swatch, which is found relative to the black color preset
+    fn color_1_matches(&self, color: Rgb<u8>) -> bool {
+        let swatch = self.capture_monitor().pixel(
+            self.black_x + self.options.color_1_offset.0,
+            self.black_y + self.options.color_1_offset.1,
+        );
+        color_difference(swatch.to_rgb(), color) <= SWATCH_TOLERANCE
+    }
+
+    fn set_max_brush_size(&mut self) {
+        self.run(|app| &app.max_brush_size, self.current_color);
+    }
+
+    fn select_brush(&mut self) {
+        self.run(|app| &app.select_brush, self.current_color);
+    }
+
+    /// Runs the steps of an action from the app profile, `color` fills in the text they type
+    fn run(&mut self, action: impl Fn(&AppProfile) -> &Vec<Step>, color: Rgb<u8>) {
+        for step in action(&self.app).clone() {
+            match step {
+                Step::Keys(presses) => {
+                    for (keys, times) in presses {
+                        for _ in 0..times {
+                            shortcut(&keys, self.input.as_mut());
+                        }
+                    }
+                }
+                Step::Type(text) => self.input.type_text(&app::fill_color(&text, color)),
+                Step::Click(Anchor::Canvas, x, y) => self.click(self.left + x, self.top + y),
+                Step::Click(Anchor::Palette, x, y) => {
+                    self.click(self.black_x + x, self.black_y + y)
+                }
+                Step::Wait(wait) => sleep(self.options.timing.get(wait)),
+            }
         }
-        self.enigo.key_click(Key::Return);
-        sleep(MEDIUM_SLEEP_TIME);
-        self.enigo.key_click(Key::DownArrow);
-        self.enigo.key_click(Key::Return);
-        self.click(self.left, self.top);
     }
 }
 
+/// Asks the user to point at the corners of the painting and the black color preset, pressing left
+/// control at each of them
+pub fn calibrate() -> Calibration {
+    let state = device_query::DeviceState::new();
+    wait_for_keyup(Keycode::LControl, &state);
+    let (mut left, mut top) = state.get_mouse().coords;
+    wait_for_keyup(Keycode::LControl, &state);
+    let (mut right, mut bottom) = state.get_mouse().coords;
+    wait_for_keyup(Keycode::LControl, &state);
+    let (black_x, black_y) = state.get_mouse().coords;
+    if right < left {
+        (right, left) = (left, right);
+    }
+    if bottom < top {
+        (top, bottom) = (bottom, top);
+    }
+    Calibration {
+        left,
+        top,
+        right,
+        bottom,
+        black_x,
+        black_y,
+    }
+}
+
+fn screen_changed(
+    before: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    after: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+) -> bool {
+    let changed = before
+        .pixels()
+        .zip(after.pixels())
+        .filter(|(before, after)| before != after)
+        .count();
+    changed as f32 > (before.width() * before.height()) as f32 * SCREEN_CHANGE_RATIO
+}
+
 fn color_difference(color_1: Rgb<u8>, color_2: Rgb<u8>) -> f32 {
     ((color_1.0[0] as f32 - color_2.0[0] as f32).abs()
         + (color_1.0[1] as f32 - color_2.0[1] as f32).abs()
@@ -564,28 +1844,32 @@ fn color_difference(color_1: Rgb<u8>, color_2: Rgb<u8>) -> f32 {
     .sqrt()
 }
 
-fn blend_with_white(color: Rgba<u8>) -> Rgb<u8> {
+fn blend_with_matte(color: Rgba<u8>, matte: Rgb<u8>) -> Rgb<u8> {
     let alpha_ratio = color.0[3] as f32 / 255.;
     *Rgb::from_slice(&[
-        (color.0[0] as f32 * alpha_ratio + 255. - 255. * alpha_ratio).floor() as u8,
-        (color.0[1] as f32 * alpha_ratio + 255. - 255. * alpha_ratio).floor() as u8,
-        (color.0[2] as f32 * alpha_ratio + 255. - 255. * alpha_ratio).floor() as u8,
+        (color.0[0] as f32 * alpha_ratio + matte.0[0] as f32 * (1. - alpha_ratio)).floor() as u8,
+        (color.0[1] as f32 * alpha_ratio + matte.0[1] as f32 * (1. - alpha_ratio)).floor() as u8,
+        (color.0[2] as f32 * alpha_ratio + matte.0[2] as f32 * (1. - alpha_ratio)).floor() as u8,
     ])
 }
 
-fn shortcut(keys: &[Key], enigo: &mut enigo::Enigo) {
-    for key in keys {
-        enigo.key_down(*key);
-    }
-    for key in keys {
-        enigo.key_up(*key);
+pub fn parse_color(hex: &str) -> Result<Rgb<u8>, String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "Invalid color \"{hex}\", expected a hex code like #ff8800"
+        ));
     }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    Ok(*Rgb::from_slice(&[channel(0), channel(2), channel(4)]))
 }
 
-fn alt_sequence(keys: &[Key], enigo: &mut enigo::Enigo) {
-    enigo.key_click(Key::Alt);
+fn shortcut(keys: &[Key], input: &mut dyn Input) {
+    for key in keys {
+        input.key_down(*key);
+    }
     for key in keys {
-        enigo.key_click(*key);
+        input.key_up(*key);
     }
 }
 
@@ -599,3 +1883,175 @@ fn wait_for_keyup(key: Keycode, state: &DeviceState) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Event, Recording};
+    use std::time::Instant;
+
+    fn artist(drag: Option<Drag>) -> (Artist, Recording) {
+        artist_for(app::built_in("mspaint").unwrap(), drag)
+    }
+
+    fn artist_for(app: AppProfile, drag: Option<Drag>) -> (Artist, Recording) {
+        let options = PaintOptions {
+            tolerance: 5.,
+            color_limit: usize::MAX,
+            background: Background::Auto,
+            matte: Rgb([255, 255, 255]),
+            overlay: false,
+            verify: false,
+            max_passes: 3,
+            verify_threshold: 0,
+            verify_tolerance: 5.,
+            report: false,
+            budget: Budget::default(),
+            sketch: None,
+            timing: Timing {
+                small_sleep: Duration::ZERO,
+                medium_sleep: Duration::ZERO,
+                long_sleep: Duration::ZERO,
+            },
+            adaptive_timing: false,
+            confirm_colors: false,
+            color_1_offset: (-85, 10),
+            dot_width: 5,
+            color_spacing: 24,
+            palette_origin: (0, 0),
+            input: InputBackend::XTest,
+            capture: CaptureBackend::X11,
+            drag,
+            exact: false,
+        };
+        let calibration = Calibration {
+            left: 0,
+            top: 0,
+            right: 100,
+            bottom: 100,
+            black_x: 0,
+            black_y: 0,
+        };
+        let recording = Recording::default();
+        let artist = Artist::with_backends(
+            ImageBuffer::new(1, 1),
+            options,
+            calibration,
+            app,
+            vec![],
+            Box::new(recording.clone()),
+            backend::capture(CaptureBackend::Xcap),
+        );
+        (artist, recording)
+    }
+
+    fn moves(recording: &Recording) -> Vec<(i32, i32)> {
+        recording
+            .events
+            .borrow()
+            .iter()
+            .map(|event| match event {
+                Event::Move(x, y) => (*x, *y),
+                _ => panic!("Expected only moves but got {event:?}"),
+            })
+            .collect()
+    }
+
+    fn steps(points: &[(i32, i32)], from: (i32, i32)) -> Vec<f32> {
+        [from]
+            .iter()
+            .chain(points)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|pair| ((pair[1].0 - pair[0].0) as f32).hypot((pair[1].1 - pair[0].1) as f32))
+            .collect()
+    }
+
+    #[test]
+    fn linear_drags_move_through_evenly_spaced_points() {
+        let (mut artist, recording) = artist(Some(Drag {
+            spacing: 10.,
+            speed: 100_000.,
+            easing: Easing::Linear,
+        }));
+        artist.drag_to((0, 0), (60, 80));
+        let points = moves(&recording);
+        assert_eq!(points.len(), 10);
+        assert_eq!(points.last(), Some(&(60, 80)));
+        for step in steps(&points, (0, 0)) {
+            assert!((step - 10.).abs() <= 1., "step of {step} pixels");
+        }
+    }
+
+    #[test]
+    fn eased_drags_are_slower_at_the_ends() {
+        let (mut artist, recording) = artist(Some(Drag {
+            spacing: 10.,
+            speed: 100_000.,
+            easing: Easing::EaseInOut,
+        }));
+        artist.drag_to((0, 0), (200, 0));
+        let points = moves(&recording);
+        assert_eq!(points.len(), 20);
+        assert_eq!(points.last(), Some(&(200, 0)));
+        let steps = steps(&points, (0, 0));
+        let middle = steps[steps.len() / 2];
+        assert!(steps[0] < middle && steps[steps.len() - 1] < middle);
+        assert_eq!(steps.iter().sum::<f32>(), 200.);
+    }
+
+    #[test]
+    fn drags_take_their_length_divided_by_the_speed() {
+        let (mut artist, _) = artist(Some(Drag {
+            spacing: 5.,
+            speed: 1000.,
+            easing: Easing::Linear,
+        }));
+        let time = artist.drag_time((10, 10), (40, 50));
+        assert!((time.as_secs_f32() - 0.05).abs() < 1e-6, "took {time:?}");
+        let start = Instant::now();
+        artist.drag_to((10, 10), (40, 50));
+        // Every move waits the same share of the time, rounded down to whole nanoseconds
+        assert!(start.elapsed() >= time - Duration::from_nanos(10));
+    }
+
+    #[test]
+    fn drags_without_spacing_move_straight_to_the_end() {
+        for drag in [
+            None,
+            Some(Drag {
+                spacing: 0.,
+                speed: 1000.,
+                easing: Easing::EaseInOut,
+            }),
+            Some(Drag {
+                spacing: -3.,
+                speed: 1000.,
+                easing: Easing::Linear,
+            }),
+        ] {
+            let (mut artist, recording) = artist(drag);
+            assert_eq!(artist.drag_time((0, 0), (300, 400)), Duration::ZERO);
+            artist.drag_to((0, 0), (300, 400));
+            assert_eq!(moves(&recording), [(300, 400)]);
+        }
+    }
+
+    #[test]
+    fn custom_colors_stay_put_when_the_presets_are_replaced() {
+        let app = app::built_in("mspaint").unwrap();
+        let (artist, _) = artist_for(app.clone(), None);
+        let custom: Vec<_> = (20..30)
+            .map(|index| artist.preset_position(index))
+            .collect();
+        let mut replaced = app;
+        replaced.replace_presets(vec![Rgb([0, 0, 0]); 6], 4, 24);
+        let (artist, _) = artist_for(replaced, None);
+        // The presets are laid out in rows of the palette
+        assert_eq!(artist.preset_position(5), (24, 24));
+        let replaced_custom: Vec<_> = (6..16).map(|index| artist.preset_position(index)).collect();
+        assert_eq!(replaced_custom, custom);
+        assert_eq!(custom[0], (0, 48));
+        assert_eq!(custom[9], (9 * 24, 48));
+    }
+}
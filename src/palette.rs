@@ -0,0 +1,332 @@
+use crate::paint::parse_color;
+use image::{ImageBuffer, Rgb, Rgba};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+
+/// The colors of a palette file, in the order they are laid out in the app
+pub struct PaletteFile {
+    pub colors: Vec<Rgb<u8>>,
+    /// How many colors are in a row, if the file says so
+    pub columns: Option<usize>,
+}
+
+/// Loads a GIMP `.gpl`, Paint.NET `.txt`, `.hex` or JASC `.pal` palette, the extension picks the
+/// format
+pub fn load(path: &str) -> PaletteFile {
+    let text = fs::read_to_string(path).unwrap_or_else(|_| panic!("Could not open palette {path}"));
+    let palette = match extension(path).as_deref() {
+        Some("gpl") => parse_gpl(&text),
+        Some("txt") => parse_paint_net(&text),
+        Some("hex") => parse_hex(&text),
+        Some("pal") => parse_jasc(&text),
+        _ => Err("Unknown palette format, expected a .gpl, .txt, .hex or .pal file".to_string()),
+    }
+    .unwrap_or_else(|error| panic!("Invalid palette {path}: {error}"));
+    if palette.colors.is_empty() {
+        panic!("The palette {path} has no colors");
+    }
+    palette
+}
+
+/// Saves the colors as a GIMP `.gpl`, Adobe `.ase` or `.hex` palette, the extension picks the
+/// format
+pub fn save(path: &str, colors: &[Rgb<u8>]) {
+    let bytes = match extension(path).as_deref() {
+        Some("gpl") => {
+            let name = Path::new(path).file_stem().unwrap().to_string_lossy();
+            let mut text = format!("GIMP Palette\nName: {name}\n#\n");
+            for color in colors {
+                let [r, g, b] = color.0;
+                text += &format!("{r:3} {g:3} {b:3}\t{}\n", hex(*color));
+            }
+            text.into_bytes()
+        }
+        Some("hex") => colors
+            .iter()
+            .map(|color| hex(*color)[1..].to_string() + "\n")
+            .collect::<String>()
+            .into_bytes(),
+        Some("ase") => ase(colors),
+        _ => panic!("Unknown palette format for {path}, expected a .gpl, .ase or .hex file"),
+    };
+    fs::write(path, bytes).expect("Could not save palette");
+}
+
+/// Saves the image as a PNG that indexes into the colors, transparent pixels get an extra fully
+/// transparent entry after them
+pub fn save_indexed(path: &str, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, colors: &[Rgb<u8>]) {
+    let has_transparent = image.pixels().any(|pixel| pixel.0[3] == 0);
+    if colors.len() + has_transparent as usize > 256 {
+        panic!(
+            "The dots use {} colors, an indexed PNG can only hold 256",
+            colors.len()
+        );
+    }
+    let transparent = colors.len();
+    let indices: Vec<u8> = image
+        .pixels()
+        .map(|pixel| match pixel.0 {
+            [.., 0] => transparent,
+            [r, g, b, _] => colors
+                .iter()
+                .position(|color| color.0 == [r, g, b])
+                .expect("The image has a color that is not in the palette"),
+        })
+        .map(|index| index as u8)
+        .collect();
+    let file = File::create(path).expect("Could not save dots");
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width(), image.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut palette: Vec<u8> = colors.iter().flat_map(|color| color.0).collect();
+    if has_transparent {
+        palette.extend([0, 0, 0]);
+        let mut alpha = vec![255; colors.len()];
+        alpha.push(0);
+        encoder.set_trns(alpha);
+    }
+    encoder.set_palette(palette);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&indices).unwrap();
+    writer.finish().unwrap();
+}
+
+/// An Adobe Swatch Exchange file with every color as an RGB swatch named by its hex code
+fn ase(colors: &[Rgb<u8>]) -> Vec<u8> {
+    let mut bytes = b"ASEF".to_vec();
+    bytes.extend(1u16.to_be_bytes());
+    bytes.extend(0u16.to_be_bytes());
+    bytes.extend((colors.len() as u32).to_be_bytes());
+    for color in colors {
+        let name: Vec<u16> = hex(*color).encode_utf16().chain([0]).collect();
+        let mut block = (name.len() as u16).to_be_bytes().to_vec();
+        block.extend(name.iter().flat_map(|unit| unit.to_be_bytes()));
+        block.extend(b"RGB ");
+        for channel in color.0 {
+            block.extend((channel as f32 / 255.).to_be_bytes());
+        }
+        // Normal, as opposed to global or spot colors
+        block.extend(2u16.to_be_bytes());
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend((block.len() as u32).to_be_bytes());
+        bytes.extend(block);
+    }
+    bytes
+}
+
+fn hex(color: Rgb<u8>) -> String {
+    let [r, g, b] = color.0;
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+}
+
+fn parse_gpl(text: &str) -> Result<PaletteFile, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("Missing the GIMP Palette header".to_string());
+    }
+    let mut colors = vec![];
+    let mut columns = None;
+    for line in lines.map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Columns:") {
+            let value: usize = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid columns \"{line}\""))?;
+            columns = Some(value).filter(|columns| *columns > 0);
+            continue;
+        }
+        colors.push(parse_rgb(line)?);
+    }
+    Ok(PaletteFile { colors, columns })
+}
+
+/// Every line is a color as AARRGGBB, lines starting with ; are comments
+fn parse_paint_net(text: &str) -> Result<PaletteFile, String> {
+    let colors = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(|line| match line.get(2..) {
+            Some(rgb) if line.len() == 8 => parse_color(rgb),
+            _ => parse_color(line),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(PaletteFile {
+        colors,
+        columns: None,
+    })
+}
+
+fn parse_hex(text: &str) -> Result<PaletteFile, String> {
+    let colors = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(parse_color)
+        .collect::<Result<_, _>>()?;
+    Ok(PaletteFile {
+        colors,
+        columns: None,
+    })
+}
+
+fn parse_jasc(text: &str) -> Result<PaletteFile, String> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("JASC-PAL") {
+        return Err("Missing the JASC-PAL header, binary .pal files are not supported".to_string());
+    }
+    lines.next();
+    let count: usize = lines
+        .next()
+        .and_then(|count| count.parse().ok())
+        .ok_or("Missing the number of colors")?;
+    let colors = lines
+        .filter(|line| !line.is_empty())
+        .take(count)
+        .map(parse_rgb)
+        .collect::<Result<Vec<_>, _>>()?;
+    if colors.len() != count {
+        return Err(format!(
+            "Expected {count} colors but found {}",
+            colors.len()
+        ));
+    }
+    Ok(PaletteFile {
+        colors,
+        columns: None,
+    })
+}
+
+/// A color written as red, green and blue from 0 to 255, anything after them is its name
+fn parse_rgb(line: &str) -> Result<Rgb<u8>, String> {
+    let channels: Vec<u8> = line
+        .split_whitespace()
+        .take(3)
+        .map(|channel| channel.parse().ok())
+        .collect::<Option<_>>()
+        .filter(|channels: &Vec<u8>| channels.len() == 3)
+        .ok_or(format!("Invalid color \"{line}\""))?;
+    Ok(Rgb([channels[0], channels[1], channels[2]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Pixel;
+
+    const COLORS: [Rgb<u8>; 3] = [Rgb([0, 0, 0]), Rgb([255, 128, 7]), Rgb([18, 52, 86])];
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("artist-{}-{name}", std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn round_trip(name: &str) -> PaletteFile {
+        let path = temp_path(name);
+        save(&path, &COLORS);
+        let palette = load(&path);
+        fs::remove_file(&path).unwrap();
+        palette
+    }
+
+    #[test]
+    fn gpl_and_hex_palettes_load_what_was_saved() {
+        assert_eq!(round_trip("palette.gpl").colors, COLORS);
+        assert_eq!(round_trip("palette.hex").colors, COLORS);
+    }
+
+    #[test]
+    fn ase_palettes_have_a_named_rgb_swatch_per_color() {
+        let bytes = ase(&COLORS);
+        assert_eq!(&bytes[..4], b"ASEF");
+        assert_eq!(bytes[8..12], 3u32.to_be_bytes());
+        let mut offset = 12;
+        for color in COLORS {
+            assert_eq!(bytes[offset..offset + 2], 1u16.to_be_bytes());
+            let length = u32::from_be_bytes(bytes[offset + 2..offset + 6].try_into().unwrap());
+            let block = &bytes[offset + 6..offset + 6 + length as usize];
+            let name_length = u16::from_be_bytes([block[0], block[1]]) as usize;
+            let name: Vec<u16> = block[2..2 + 2 * name_length]
+                .chunks(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            assert_eq!(String::from_utf16(&name).unwrap(), hex(color) + "\0");
+            let rest = &block[2 + 2 * name_length..];
+            assert_eq!(&rest[..4], b"RGB ");
+            for (channel, bytes) in color.0.iter().zip(rest[4..16].chunks(4)) {
+                let value = f32::from_be_bytes(bytes.try_into().unwrap());
+                assert_eq!((value * 255.).round() as u8, *channel);
+            }
+            offset += 6 + length as usize;
+        }
+        assert_eq!(offset, bytes.len());
+    }
+
+    #[test]
+    fn indexed_dots_decode_to_the_same_pixels() {
+        let mut image = ImageBuffer::from_fn(3, 2, |x, _| COLORS[x as usize].to_rgba());
+        image.put_pixel(1, 1, Rgba([0, 0, 0, 0]));
+        let path = temp_path("dots.png");
+        save_indexed(&path, &image, &COLORS);
+        let decoded = image::open(&path).unwrap().to_rgba8();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn gpl_palettes_read_columns_and_skip_comments() {
+        let text = "GIMP Palette\nName: Test\nColumns: 4\n#\n  0   0   0\tBlack\n255 128 7\n";
+        let palette = parse_gpl(text).unwrap();
+        assert_eq!(palette.colors, COLORS[..2]);
+        assert_eq!(palette.columns, Some(4));
+        assert_eq!(
+            parse_gpl("GIMP Palette\nColumns: 0\n").unwrap().columns,
+            None
+        );
+    }
+
+    #[test]
+    fn paint_net_palettes_drop_the_alpha() {
+        let palette = parse_paint_net("; Comment\nFF000000\nffff8007\n123456\n").unwrap();
+        assert_eq!(palette.colors, COLORS);
+    }
+
+    #[test]
+    fn jasc_palettes_have_as_many_colors_as_they_say() {
+        let palette = parse_jasc("JASC-PAL\n0100\n2\n0 0 0\n255 128 7\n").unwrap();
+        assert_eq!(palette.colors, COLORS[..2]);
+        assert!(parse_jasc("JASC-PAL\n0100\n3\n0 0 0\n255 128 7\n").is_err());
+        assert!(parse_jasc("JASC-PAL\n0100\nmany\n0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn malformed_palettes_are_errors() {
+        assert!(parse_gpl("0 0 0\n").is_err());
+        assert!(parse_gpl("GIMP Palette\nColumns: x\n").is_err());
+        assert!(parse_gpl("GIMP Palette\n0 0\n").is_err());
+        assert!(parse_gpl("GIMP Palette\n0 0 256\n").is_err());
+        assert!(parse_hex("#12345\n").is_err());
+        assert!(parse_hex("zzzzzz\n").is_err());
+        assert!(parse_paint_net("FFGG0000\n").is_err());
+        assert!(parse_jasc("RIFF").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown palette format")]
+    fn palettes_with_an_unknown_extension_are_refused() {
+        save(&temp_path("palette.aco"), &COLORS);
+    }
+}
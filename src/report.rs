@@ -0,0 +1,164 @@
+use image::{imageops, GenericImage, ImageBuffer, Rgb};
+use std::fmt;
+
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STEP: u32 = 4;
+
+pub struct Metrics {
+    pub psnr: f32,
+    pub ssim: f32,
+    pub delta_e: f32,
+}
+
+impl Metrics {
+    pub fn compare(
+        source: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+        result: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    ) -> Self {
+        Self {
+            psnr: psnr(source, result),
+            ssim: ssim(source, result),
+            delta_e: mean_delta_e(source, result),
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PSNR {:.2} dB, SSIM {:.4}, mean \u{394}E {:.2}",
+            self.psnr, self.ssim, self.delta_e
+        )
+    }
+}
+
+pub fn psnr(a: &ImageBuffer<Rgb<u8>, Vec<u8>>, b: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f32 {
+    let mut squared_error = 0.;
+    for (pixel_a, pixel_b) in a.pixels().zip(b.pixels()) {
+        for channel in 0..3 {
+            let diff = pixel_a.0[channel] as f64 - pixel_b.0[channel] as f64;
+            squared_error += diff * diff;
+        }
+    }
+    let mse = squared_error / (a.width() * a.height() * 3) as f64;
+    if mse == 0. {
+        f32::INFINITY
+    } else {
+        (10. * (255. * 255. / mse).log10()) as f32
+    }
+}
+
+pub fn ssim(a: &ImageBuffer<Rgb<u8>, Vec<u8>>, b: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f32 {
+    const C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+    const C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+    let window_width = SSIM_WINDOW.min(a.width());
+    let window_height = SSIM_WINDOW.min(a.height());
+    let mut total = 0.;
+    let mut windows = 0;
+    let mut top = 0;
+    while top + window_height <= a.height() {
+        let mut left = 0;
+        while left + window_width <= a.width() {
+            let mut values = vec![];
+            for y in top..top + window_height {
+                for x in left..left + window_width {
+                    values.push((luma(*a.get_pixel(x, y)), luma(*b.get_pixel(x, y))));
+                }
+            }
+            let count = values.len() as f64;
+            let mean_a = values.iter().map(|(a, _)| a).sum::<f64>() / count;
+            let mean_b = values.iter().map(|(_, b)| b).sum::<f64>() / count;
+            let (mut variance_a, mut variance_b, mut covariance) = (0., 0., 0.);
+            for (value_a, value_b) in &values {
+                variance_a += (value_a - mean_a) * (value_a - mean_a);
+                variance_b += (value_b - mean_b) * (value_b - mean_b);
+                covariance += (value_a - mean_a) * (value_b - mean_b);
+            }
+            variance_a /= count;
+            variance_b /= count;
+            covariance /= count;
+            total += ((2. * mean_a * mean_b + C1) * (2. * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+            left += SSIM_STEP;
+        }
+        top += SSIM_STEP;
+    }
+    (total / windows as f64) as f32
+}
+
+pub fn mean_delta_e(a: &ImageBuffer<Rgb<u8>, Vec<u8>>, b: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f32 {
+    let total: f32 = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(pixel_a, pixel_b)| delta_e(*pixel_a, *pixel_b))
+        .sum();
+    total / (a.width() * a.height()) as f32
+}
+
+/// CIE76 color difference
+pub fn delta_e(color_1: Rgb<u8>, color_2: Rgb<u8>) -> f32 {
+    let lab_1 = to_lab(color_1);
+    let lab_2 = to_lab(color_2);
+    ((lab_1[0] - lab_2[0]).powi(2) + (lab_1[1] - lab_2[1]).powi(2) + (lab_1[2] - lab_2[2]).powi(2))
+        .sqrt()
+}
+
+fn to_lab(color: Rgb<u8>) -> [f32; 3] {
+    let linear = color.0.map(|channel| {
+        let channel = channel as f32 / 255.;
+        if channel <= 0.04045 {
+            channel / 12.92
+        } else {
+            ((channel + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (0.4124 * linear[0] + 0.3576 * linear[1] + 0.1805 * linear[2]) / 0.95047;
+    let y = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+    let z = (0.0193 * linear[0] + 0.1192 * linear[1] + 0.9505 * linear[2]) / 1.08883;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16. / 116.
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
+
+fn luma(color: Rgb<u8>) -> f64 {
+    0.299 * color.0[0] as f64 + 0.587 * color.0[1] as f64 + 0.114 * color.0[2] as f64
+}
+
+pub fn difference_image(
+    a: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    b: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(a.width(), a.height(), |x, y| {
+        let diff = delta_e(*a.get_pixel(x, y), *b.get_pixel(x, y));
+        let value = (diff * 255. / 50.).min(255.) as u8;
+        Rgb([value, value, value])
+    })
+}
+
+pub fn side_by_side(
+    images: &[&ImageBuffer<Rgb<u8>, Vec<u8>>],
+    scale: u32,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let width = images[0].width() * scale;
+    let height = images[0].height() * scale;
+    let mut combined = ImageBuffer::from_pixel(
+        width * images.len() as u32 + scale * (images.len() as u32 - 1),
+        height,
+        Rgb([255, 0, 255]),
+    );
+    for (i, img) in images.iter().enumerate() {
+        let scaled = imageops::resize(*img, width, height, imageops::FilterType::Nearest);
+        combined
+            .copy_from(&scaled, i as u32 * (width + scale), 0)
+            .unwrap();
+    }
+    combined
+}
@@ -0,0 +1,159 @@
+use image::{ImageBuffer, Luma, Rgb};
+
+/// Finds the edges in the image and traces them into strokes, the points are in the coordinates
+/// of the image
+pub fn trace_edges(img: &ImageBuffer<Rgb<u8>, Vec<u8>>, threshold: f32) -> Vec<Vec<(i32, i32)>> {
+    let mut edges = detect_edges(img, threshold);
+    thin(&mut edges);
+    trace(&mut edges)
+}
+
+/// Sobel edge detection on the brightness of the image
+fn detect_edges(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+    threshold: f32,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let (width, height) = (img.width() as i32, img.height() as i32);
+    let brightness = |x: i32, y: i32| {
+        let color = img.get_pixel(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+        0.299 * color.0[0] as f32 + 0.587 * color.0[1] as f32 + 0.114 * color.0[2] as f32
+    };
+    ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        let gradient_x =
+            brightness(x + 1, y - 1) + 2. * brightness(x + 1, y) + brightness(x + 1, y + 1)
+                - brightness(x - 1, y - 1)
+                - 2. * brightness(x - 1, y)
+                - brightness(x - 1, y + 1);
+        let gradient_y =
+            brightness(x - 1, y + 1) + 2. * brightness(x, y + 1) + brightness(x + 1, y + 1)
+                - brightness(x - 1, y - 1)
+                - 2. * brightness(x, y - 1)
+                - brightness(x + 1, y - 1);
+        if (gradient_x * gradient_x + gradient_y * gradient_y).sqrt() > threshold {
+            Luma([1])
+        } else {
+            Luma([0])
+        }
+    })
+}
+
+/// Zhang-Suen thinning, shrinks the edges down to lines one pixel wide
+fn thin(edges: &mut ImageBuffer<Luma<u8>, Vec<u8>>) {
+    let (width, height) = (edges.width() as i32, edges.height() as i32);
+    let get = |edges: &ImageBuffer<Luma<u8>, Vec<u8>>, x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < width && y < height && edges.get_pixel(x as u32, y as u32).0[0] == 1
+    };
+    loop {
+        let mut changed = false;
+        for step in 0..2 {
+            let mut removed = vec![];
+            for y in 0..height {
+                for x in 0..width {
+                    if !get(edges, x, y) {
+                        continue;
+                    }
+                    // Neighbours clockwise from the top
+                    let neighbours = [
+                        get(edges, x, y - 1),
+                        get(edges, x + 1, y - 1),
+                        get(edges, x + 1, y),
+                        get(edges, x + 1, y + 1),
+                        get(edges, x, y + 1),
+                        get(edges, x - 1, y + 1),
+                        get(edges, x - 1, y),
+                        get(edges, x - 1, y - 1),
+                    ];
+                    let count = neighbours.iter().filter(|set| **set).count();
+                    let transitions = (0..8)
+                        .filter(|i| !neighbours[*i] && neighbours[(i + 1) % 8])
+                        .count();
+                    let [top, _, right, _, bottom, _, left, _] = neighbours;
+                    let removable = if step == 0 {
+                        !(right && bottom && (top || left))
+                    } else {
+                        !(top && left && (right || bottom))
+                    };
+                    if (2..=6).contains(&count) && transitions == 1 && removable {
+                        removed.push((x as u32, y as u32));
+                    }
+                }
+            }
+            changed |= !removed.is_empty();
+            for (x, y) in removed {
+                edges.put_pixel(x, y, Luma([0]));
+            }
+        }
+        if !changed {
+            return;
+        }
+    }
+}
+
+/// Follows the thinned edges into strokes, starting from line ends so lines become a single stroke
+fn trace(edges: &mut ImageBuffer<Luma<u8>, Vec<u8>>) -> Vec<Vec<(i32, i32)>> {
+    const NEIGHBOURS: [(i32, i32); 8] = [
+        (0, -1),
+        (1, 0),
+        (0, 1),
+        (-1, 0),
+        (1, -1),
+        (1, 1),
+        (-1, 1),
+        (-1, -1),
+    ];
+    let (width, height) = (edges.width() as i32, edges.height() as i32);
+    let get = |edges: &ImageBuffer<Luma<u8>, Vec<u8>>, x: i32, y: i32| {
+        x >= 0 && y >= 0 && x < width && y < height && edges.get_pixel(x as u32, y as u32).0[0] == 1
+    };
+    let neighbour_count = |edges: &ImageBuffer<Luma<u8>, Vec<u8>>, x: i32, y: i32| {
+        NEIGHBOURS
+            .iter()
+            .filter(|(dx, dy)| get(edges, x + dx, y + dy))
+            .count()
+    };
+    let mut strokes = vec![];
+    for ends_only in [true, false] {
+        for y in 0..height {
+            for x in 0..width {
+                if !get(edges, x, y) || (ends_only && neighbour_count(edges, x, y) != 1) {
+                    continue;
+                }
+                let mut stroke = vec![(x, y)];
+                edges.put_pixel(x as u32, y as u32, Luma([0]));
+                let (mut current_x, mut current_y) = (x, y);
+                while let Some((dx, dy)) = NEIGHBOURS
+                    .iter()
+                    .find(|(dx, dy)| get(edges, current_x + dx, current_y + dy))
+                {
+                    (current_x, current_y) = (current_x + dx, current_y + dy);
+                    edges.put_pixel(current_x as u32, current_y as u32, Luma([0]));
+                    stroke.push((current_x, current_y));
+                }
+                // Closes loops, which end next to where they started
+                if stroke.len() > 2 && (current_x - x).abs() <= 1 && (current_y - y).abs() <= 1 {
+                    stroke.push((x, y));
+                }
+                strokes.push(simplify(stroke));
+            }
+        }
+    }
+    strokes
+}
+
+/// Removes the points in the middle of straight runs since the drag goes through them anyway
+fn simplify(stroke: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let mut simplified: Vec<(i32, i32)> = vec![];
+    for point in stroke {
+        if simplified.len() >= 2 {
+            let (x_1, y_1) = simplified[simplified.len() - 2];
+            let (x_2, y_2) = simplified[simplified.len() - 1];
+            let direction = ((x_2 - x_1).signum(), (y_2 - y_1).signum());
+            if direction == ((point.0 - x_2).signum(), (point.1 - y_2).signum()) {
+                simplified.pop();
+            }
+        }
+        simplified.push(point);
+    }
+    simplified
+}
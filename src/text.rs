@@ -0,0 +1,62 @@
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{ImageBuffer, Rgb, Rgba};
+use std::fs;
+
+/// Rasterizes the text in the color on a transparent background, every line of the text is laid
+/// out below the one before it
+pub fn render(
+    text: &str,
+    font_path: &str,
+    size: f32,
+    color: Rgb<u8>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let font = FontVec::try_from_vec(fs::read(font_path).expect("Could not open font"))
+        .expect("Invalid font");
+    let font = font.as_scaled(PxScale::from(size));
+    let line_height = font.height() + font.line_gap();
+    let mut glyphs = vec![];
+    for (line_index, line) in text.lines().enumerate() {
+        let baseline = font.ascent() + line_height * line_index as f32;
+        let mut caret = 0.;
+        let mut previous = None;
+        for character in line.chars() {
+            let id = font.glyph_id(character);
+            if let Some(previous) = previous {
+                caret += font.kern(previous, id);
+            }
+            glyphs.push(id.with_scale_and_position(font.scale(), point(caret, baseline)));
+            caret += font.h_advance(id);
+            previous = Some(id);
+        }
+    }
+    let outlines: Vec<_> = glyphs
+        .into_iter()
+        .filter_map(|glyph| font.outline_glyph(glyph))
+        .collect();
+    if outlines.is_empty() {
+        panic!("The text has nothing to paint");
+    }
+    let right = outlines
+        .iter()
+        .map(|outline| outline.px_bounds().max.x)
+        .fold(0., f32::max);
+    let bottom = line_height * text.lines().count() as f32;
+    let mut img = ImageBuffer::from_pixel(
+        right.ceil() as u32 + 1,
+        bottom.ceil() as u32 + 1,
+        Rgba([color.0[0], color.0[1], color.0[2], 0]),
+    );
+    for outline in outlines {
+        let bounds = outline.px_bounds();
+        outline.draw(|x, y, coverage| {
+            let x = bounds.min.x as i32 + x as i32;
+            let y = bounds.min.y as i32 + y as i32;
+            if x < 0 || y < 0 || x >= img.width() as i32 || y >= img.height() as i32 {
+                return;
+            }
+            let pixel = img.get_pixel_mut(x as u32, y as u32);
+            pixel.0[3] = pixel.0[3].max((coverage.min(1.) * 255.).round() as u8);
+        });
+    }
+    img
+}
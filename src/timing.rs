@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+const MIN_SLEEP_TIME: Duration = Duration::from_millis(1);
+const MAX_SLEEP_TIME: Duration = Duration::from_secs(2);
+/// How many checks in a row have to pass before a wait is shortened
+const SPEED_UP_STREAK: usize = 8;
+
+/// How long to wait for paint to catch up after different inputs
+#[derive(Clone, Copy)]
+pub struct Timing {
+    pub small_sleep: Duration,
+    pub medium_sleep: Duration,
+    pub long_sleep: Duration,
+}
+
+#[derive(Clone, Copy)]
+pub enum Wait {
+    /// After mouse inputs like strokes
+    Small,
+    /// After opening dialogs and menus
+    Medium,
+    /// Before taking screenshots
+    Long,
+}
+
+impl Wait {
+    fn name(&self) -> &'static str {
+        match self {
+            Wait::Small => "small wait (strokes)",
+            Wait::Medium => "medium wait (dialogs)",
+            Wait::Long => "long wait (screenshots)",
+        }
+    }
+}
+
+impl Timing {
+    pub fn get(&self, wait: Wait) -> Duration {
+        match wait {
+            Wait::Small => self.small_sleep,
+            Wait::Medium => self.medium_sleep,
+            Wait::Long => self.long_sleep,
+        }
+    }
+
+    fn get_mut(&mut self, wait: Wait) -> &mut Duration {
+        match wait {
+            Wait::Small => &mut self.small_sleep,
+            Wait::Medium => &mut self.medium_sleep,
+            Wait::Long => &mut self.long_sleep,
+        }
+    }
+}
+
+/// Tunes the waits from screenshots that show whether paint kept up with the inputs, a wait is
+/// lengthened every time paint fell behind and shortened again after enough checks in a row passed
+pub struct TimingController {
+    initial: Timing,
+    checks: [usize; 3],
+    failures: [usize; 3],
+    streaks: [usize; 3],
+    adjustments: Vec<String>,
+}
+
+impl TimingController {
+    pub fn new(timing: Timing) -> Self {
+        Self {
+            initial: timing,
+            checks: [0; 3],
+            failures: [0; 3],
+            streaks: [0; 3],
+            adjustments: vec![],
+        }
+    }
+
+    /// `problem` describes what went wrong when paint didn't keep up, it is shown in the report
+    pub fn record(&mut self, timing: &mut Timing, wait: Wait, kept_up: bool, problem: &str) {
+        let index = wait as usize;
+        self.checks[index] += 1;
+        let sleep = timing.get_mut(wait);
+        let old = *sleep;
+        let reason = if kept_up {
+            self.streaks[index] += 1;
+            if self.streaks[index] < SPEED_UP_STREAK {
+                return;
+            }
+            self.streaks[index] = 0;
+            *sleep = sleep.mul_f32(0.8).max(MIN_SLEEP_TIME);
+            format!("{SPEED_UP_STREAK} checks in a row passed")
+        } else {
+            self.failures[index] += 1;
+            self.streaks[index] = 0;
+            *sleep = (sleep.mul_f32(1.5) + MIN_SLEEP_TIME).min(MAX_SLEEP_TIME);
+            problem.to_string()
+        };
+        if *sleep != old {
+            self.adjustments.push(format!(
+                "{} {:?} -> {:?} after check {}: {reason}",
+                wait.name(),
+                old,
+                *sleep,
+                self.checks[index]
+            ));
+        }
+    }
+
+    pub fn report(&self, timing: &Timing) {
+        println!("Adaptive timing:");
+        for wait in [Wait::Small, Wait::Medium, Wait::Long] {
+            let index = wait as usize;
+            println!(
+                "  {}: {:?} -> {:?}, {} of {} checks found paint behind",
+                wait.name(),
+                self.initial.get(wait),
+                timing.get(wait),
+                self.failures[index],
+                self.checks[index]
+            );
+        }
+        for adjustment in &self.adjustments {
+            println!("  {adjustment}");
+        }
+    }
+}
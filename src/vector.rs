@@ -0,0 +1,184 @@
+use image::{ImageBuffer, Rgb, Rgba};
+use resvg::{
+    tiny_skia::{self, PathSegment, Pixmap, Point, Transform},
+    usvg::{self, Node, Paint},
+};
+use std::{fs, path::Path};
+
+const MIN_RASTER_SIZE: f32 = 512.;
+const FLATTEN_STEP: f32 = 2.;
+
+pub struct Stroke {
+    pub color: Rgb<u8>,
+    pub points: Vec<(f32, f32)>,
+}
+
+/// An svg split into the filled shapes, which are painted as dots, and the strokes, which are
+/// painted as mouse drags
+pub struct Svg {
+    pub fills: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub strokes: Vec<Stroke>,
+    pub width: f32,
+    pub height: f32,
+}
+
+pub fn is_svg(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+}
+
+impl Svg {
+    pub fn open(path: &str) -> Self {
+        let data = fs::read(path).expect("Could not open svg");
+        let tree = usvg::Tree::from_data(&data, &usvg::Options::default()).expect("Invalid svg");
+        let (width, height) = (tree.size().width(), tree.size().height());
+        let scale = (MIN_RASTER_SIZE / width.min(height)).max(1.);
+        let mut pixmap = Pixmap::new(
+            (width * scale).ceil() as u32,
+            (height * scale).ceil() as u32,
+        )
+        .unwrap();
+        let mut strokes = vec![];
+        collect(
+            tree.root(),
+            &mut pixmap,
+            Transform::from_scale(scale, scale),
+            &mut strokes,
+        );
+        let fills = ImageBuffer::from_fn(pixmap.width(), pixmap.height(), |x, y| {
+            let pixel = pixmap.pixel(x, y).unwrap().demultiply();
+            Rgba([pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()])
+        });
+        Self {
+            fills,
+            strokes,
+            width,
+            height,
+        }
+    }
+}
+
+fn collect(group: &usvg::Group, pixmap: &mut Pixmap, scale: Transform, strokes: &mut Vec<Stroke>) {
+    for node in group.children() {
+        match node {
+            Node::Group(group) => collect(group, pixmap, scale, strokes),
+            Node::Path(path) if path.is_visible() => {
+                let transform = path.abs_transform().post_concat(scale);
+                if let Some(fill) = path.fill() {
+                    let mut paint = tiny_skia::Paint::default();
+                    let color = paint_color(fill.paint());
+                    paint.set_color_rgba8(color.0[0], color.0[1], color.0[2], 255);
+                    paint.anti_alias = false;
+                    let rule = match fill.rule() {
+                        usvg::FillRule::NonZero => tiny_skia::FillRule::Winding,
+                        usvg::FillRule::EvenOdd => tiny_skia::FillRule::EvenOdd,
+                    };
+                    pixmap.fill_path(path.data(), &paint, rule, transform, None);
+                }
+                if let Some(stroke) = path.stroke() {
+                    let color = paint_color(stroke.paint());
+                    let transform = path.abs_transform();
+                    for points in flatten(path.data(), transform) {
+                        strokes.push(Stroke { color, points });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Gradients and patterns can't be painted so they use the color of their first stop or black
+fn paint_color(paint: &Paint) -> Rgb<u8> {
+    let color = match paint {
+        Paint::Color(color) => *color,
+        Paint::LinearGradient(gradient) => gradient
+            .stops()
+            .first()
+            .map_or(usvg::Color::black(), |stop| stop.color()),
+        Paint::RadialGradient(gradient) => gradient
+            .stops()
+            .first()
+            .map_or(usvg::Color::black(), |stop| stop.color()),
+        Paint::Pattern(_) => usvg::Color::black(),
+    };
+    Rgb([color.red, color.green, color.blue])
+}
+
+/// Turns a path into polylines, one for each subpath
+fn flatten(path: &tiny_skia::Path, transform: Transform) -> Vec<Vec<(f32, f32)>> {
+    let mut polylines = vec![];
+    let mut current: Vec<(f32, f32)> = vec![];
+    let mut start = Point::zero();
+    let mut last = Point::zero();
+    let push = |current: &mut Vec<(f32, f32)>, point: Point| {
+        let mut point = point;
+        transform.map_point(&mut point);
+        current.push((point.x, point.y));
+    };
+    for segment in path.segments() {
+        match segment {
+            PathSegment::MoveTo(point) => {
+                if current.len() > 1 {
+                    polylines.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                push(&mut current, point);
+                (start, last) = (point, point);
+            }
+            PathSegment::LineTo(point) => {
+                push(&mut current, point);
+                last = point;
+            }
+            PathSegment::QuadTo(control, point) => {
+                let steps = curve_steps(&[last, control, point]);
+                for step in 1..=steps {
+                    let t = step as f32 / steps as f32;
+                    let mt = 1. - t;
+                    push(
+                        &mut current,
+                        Point::from_xy(
+                            mt * mt * last.x + 2. * mt * t * control.x + t * t * point.x,
+                            mt * mt * last.y + 2. * mt * t * control.y + t * t * point.y,
+                        ),
+                    );
+                }
+                last = point;
+            }
+            PathSegment::CubicTo(control_1, control_2, point) => {
+                let steps = curve_steps(&[last, control_1, control_2, point]);
+                for step in 1..=steps {
+                    let t = step as f32 / steps as f32;
+                    let mt = 1. - t;
+                    let (a, b, c, d) = (mt * mt * mt, 3. * mt * mt * t, 3. * mt * t * t, t * t * t);
+                    push(
+                        &mut current,
+                        Point::from_xy(
+                            a * last.x + b * control_1.x + c * control_2.x + d * point.x,
+                            a * last.y + b * control_1.y + c * control_2.y + d * point.y,
+                        ),
+                    );
+                }
+                last = point;
+            }
+            PathSegment::Close => {
+                push(&mut current, start);
+                last = start;
+            }
+        }
+    }
+    if current.len() > 1 {
+        polylines.push(current);
+    }
+    polylines
+}
+
+/// How many line segments a curve is split into, based on the length of its control polygon
+fn curve_steps(points: &[Point]) -> usize {
+    let length: f32 = points
+        .windows(2)
+        .map(|pair| pair[0].distance(pair[1]))
+        .sum();
+    ((length / FLATTEN_STEP).ceil() as usize).max(1)
+}